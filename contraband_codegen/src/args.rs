@@ -1,4 +1,5 @@
 use proc_macro2::Span;
+use quote::ToTokens;
 use syn::{AttributeArgs, NestedMeta};
 
 pub(crate) struct Args {
@@ -80,6 +81,63 @@ impl Args {
     }
 }
 
+/// Removes the attributes `#[name(A, B, ...)]` from `attrs` and returns the paths listed by them,
+/// e.g. the guards of `#[use_guards(AuthGuard)]`.
+pub(crate) fn take_paths(
+    attrs: &mut Vec<syn::Attribute>,
+    name: &str,
+) -> syn::Result<Vec<syn::Path>> {
+    let mut paths = Vec::new();
+    let mut err = None;
+    attrs.retain(|attr| {
+        if !attr.path.is_ident(name) {
+            return true;
+        }
+        match attr.parse_meta() {
+            Ok(syn::Meta::List(list)) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(syn::Meta::Path(path)) => paths.push(path),
+                        nested => {
+                            err = Some(syn::Error::new_spanned(
+                                nested,
+                                format!("Attribute {} expects types.", name),
+                            ))
+                        }
+                    }
+                }
+            }
+            Ok(meta) => {
+                err = Some(syn::Error::new_spanned(
+                    meta,
+                    format!("Attribute {} expects a list of types.", name),
+                ))
+            }
+            Err(e) => err = Some(e),
+        }
+        false
+    });
+    match err {
+        Some(err) => Err(err),
+        None => Ok(paths),
+    }
+}
+
+/// Removes duplicates from `paths`, keeping the first occurrence of each path.
+pub(crate) fn unique_paths<'a>(paths: impl Iterator<Item = &'a syn::Path>) -> Vec<&'a syn::Path> {
+    let mut unique: Vec<&syn::Path> = Vec::new();
+    for path in paths {
+        let key = path.to_token_stream().to_string();
+        if !unique
+            .iter()
+            .any(|other| other.to_token_stream().to_string() == key)
+        {
+            unique.push(path);
+        }
+    }
+    unique
+}
+
 impl Default for Args {
     fn default() -> Self {
         Self {
@@ -9,48 +9,289 @@ impl<'a> ToTokens for InjectedBody<'a> {
             imported_graph_ident,
             fields,
         } = self;
-        for field in fields {
-            let ident = &field.ident;
-            let ty = &field.ty;
-            let out = quote! {
-                #ident: #graph_ident
-                    .get_node::<#ty>()
-                    .or_else(|| contraband::graph::Graph::search_all(#imported_graph_ident))
-                    .unwrap()
-                    .to_owned(),
+        for InjectedField { member, ty, source } in fields {
+            let field_name = member_name(member);
+            let out = match source {
+                Source::Default => quote! {
+                    #member: std::default::Default::default(),
+                },
+                Source::With(path) => quote! {
+                    #member: #path(),
+                },
+                Source::Graph {
+                    name: Some(name),
+                    optional: true,
+                } => quote! {
+                    #member: #graph_ident.inject_named::<
+                        <#ty as contraband::graph::Optional>::Inner
+                    >(#name, #imported_graph_ident),
+                },
+                Source::Graph {
+                    name: Some(name),
+                    optional: false,
+                } => quote! {
+                    #member: #graph_ident
+                        .inject_named::<#ty>(#name, #imported_graph_ident)
+                        .ok_or_else(|| {
+                            contraband::graph::ResolutionError::missing_named_field::<#ty, Self>(
+                                #field_name,
+                                #name,
+                            )
+                        })?,
+                },
+                Source::Graph {
+                    name: None,
+                    optional: true,
+                } => quote! {
+                    #member: #graph_ident.inject::<
+                        <#ty as contraband::graph::Optional>::Inner
+                    >(#imported_graph_ident)?,
+                },
+                Source::Graph {
+                    name: None,
+                    optional: false,
+                } if is_lazy(ty) => quote! {
+                    #member: <#ty>::deferred::<Self>(#graph_ident, #field_name),
+                },
+                Source::Graph {
+                    name: None,
+                    optional: false,
+                } => quote! {
+                    #member: #graph_ident
+                        .inject::<#ty>(#imported_graph_ident)?
+                        .ok_or_else(|| {
+                            contraband::graph::ResolutionError::missing_field::<#ty, Self>(
+                                #field_name,
+                            )
+                        })?,
+                },
             };
             stream.extend(out);
         }
     }
 }
 
+/// Whether the last segment of the path of `ty` is `ident`, e.g. `Option` for `Option<T>`.
+fn is_type(ty: &syn::Type, ident: &str) -> bool {
+    match ty {
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .map(|segment| segment.ident == ident)
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// Whether `ty` is a `contraband::graph::Lazy<T>`, which is resolved after the module has been
+/// built. The type is matched by its path, either imported as `Lazy<T>` or qualified by
+/// `graph::` or `contraband::graph::`, so that e.g. `once_cell::sync::Lazy<T>` is injected as any
+/// other type.
+fn is_lazy(ty: &syn::Type) -> bool {
+    let path = match ty {
+        syn::Type::Path(syn::TypePath { qself: None, path }) => path,
+        _ => return false,
+    };
+    let idents: Vec<_> = path
+        .segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect();
+    let qualified = match idents.as_slice() {
+        [lazy] => path.leading_colon.is_none() && lazy == "Lazy",
+        [graph, lazy] => path.leading_colon.is_none() && graph == "graph" && lazy == "Lazy",
+        [contraband, graph, lazy] => {
+            contraband == "contraband" && graph == "graph" && lazy == "Lazy"
+        }
+        _ => false,
+    };
+    let single_type_argument = match path.segments.last().map(|segment| &segment.arguments) {
+        Some(syn::PathArguments::AngleBracketed(args)) => {
+            args.args.len() == 1 && matches!(args.args[0], syn::GenericArgument::Type(_))
+        }
+        _ => false,
+    };
+    qualified && single_type_argument
+}
+
+/// The name of `member` as reported in resolution errors, e.g. `0` for the first field of a
+/// tuple struct.
+fn member_name(member: &syn::Member) -> syn::LitStr {
+    let name = match member {
+        syn::Member::Named(ident) => ident.to_string(),
+        syn::Member::Unnamed(index) => index.index.to_string(),
+    };
+    syn::LitStr::new(&name, proc_macro2::Span::call_site())
+}
+
+/// Where the value of a field is taken from.
+enum Source {
+    /// The provider of the field type, with the name given by `name = "..."`. Optional fields of
+    /// type `Option<T>`, which may be annotated with `optional`, are injected as `None` when no
+    /// provider is reachable.
+    Graph {
+        name: Option<syn::LitStr>,
+        optional: bool,
+    },
+    /// `Default::default()`, given by `default`.
+    Default,
+    /// A call to the function given by `with = "..."`.
+    With(syn::ExprPath),
+}
+
+/// A field of an injectable struct together with the arguments of its `#[inject(...)]`
+/// attributes.
+struct InjectedField {
+    member: syn::Member,
+    ty: syn::Type,
+    source: Source,
+}
+
+impl InjectedField {
+    fn parse(member: syn::Member, field: &syn::Field) -> syn::Result<Self> {
+        let mut name = None;
+        let optional = is_type(&field.ty, "Option");
+        let mut source = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("inject"))
+        {
+            let nested = match attr.parse_meta()? {
+                syn::Meta::List(list) => list.nested,
+                meta => {
+                    return Err(syn::Error::new_spanned(
+                        meta,
+                        "Expected `#[inject(...)]` with arguments.",
+                    ))
+                }
+            };
+            for meta in nested {
+                match meta {
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(lit),
+                        ..
+                    })) if path.is_ident("name") => name = Some(lit),
+                    syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(lit),
+                        ..
+                    })) if path.is_ident("with") => source = Some(Source::With(lit.parse()?)),
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("optional") => {
+                        if !optional {
+                            return Err(syn::Error::new_spanned(
+                                &field.ty,
+                                "Optional dependencies must be of type `Option<T>`.",
+                            ));
+                        }
+                    }
+                    syn::NestedMeta::Meta(syn::Meta::Path(path)) if path.is_ident("default") => {
+                        source = Some(Source::Default)
+                    }
+                    meta => {
+                        return Err(syn::Error::new_spanned(
+                            meta,
+                            "Unknown inject argument, expected `name = \"...\"`, `optional`, \
+                             `default` or `with = \"...\"`.",
+                        ))
+                    }
+                }
+            }
+        }
+        let source = match source {
+            Some(_) if name.is_some() => {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "Fields that are not injected cannot be named.",
+                ))
+            }
+            Some(source) => source,
+            None if (name.is_some() || optional) && is_lazy(&field.ty) => {
+                return Err(syn::Error::new_spanned(
+                    &field.ty,
+                    "Named and optional dependencies cannot be lazy.",
+                ))
+            }
+            None => Source::Graph { name, optional },
+        };
+        Ok(Self {
+            member,
+            ty: field.ty.clone(),
+            source,
+        })
+    }
+}
+
 pub(crate) struct InjectedBody<'a> {
     graph_ident: &'a Ident,
     imported_graph_ident: &'a Ident,
-    fields: Vec<syn::Field>,
+    fields: Vec<InjectedField>,
 }
 
 impl<'a> InjectedBody<'a> {
+    pub(crate) fn dependencies(&self) -> TokenStream2 {
+        let deps = self
+            .fields
+            .iter()
+            .filter_map(|InjectedField { member, ty, source }| {
+                let field_name = member_name(member);
+                let dependency = match source {
+                    Source::Graph {
+                        name: Some(name),
+                        optional: true,
+                    } => quote! {
+                        contraband::graph::Dependency::named::<
+                            <#ty as contraband::graph::Optional>::Inner
+                        >(#field_name, #name).optional()
+                    },
+                    Source::Graph {
+                        name: Some(name),
+                        optional: false,
+                    } => quote! {
+                        contraband::graph::Dependency::named::<#ty>(#field_name, #name)
+                    },
+                    Source::Graph {
+                        name: None,
+                        optional: true,
+                    } => quote! {
+                        contraband::graph::Dependency::of::<
+                            <#ty as contraband::graph::Optional>::Inner
+                        >(#field_name).optional()
+                    },
+                    Source::Graph {
+                        name: None,
+                        optional: false,
+                    } => quote! {
+                        contraband::graph::Dependency::of::<#ty>(#field_name)
+                    },
+                    Source::Default | Source::With(_) => return None,
+                };
+                Some(dependency)
+            });
+        quote! {
+            vec![#(#deps),*]
+        }
+    }
+
     pub(crate) fn new(
         graph_ident: &'a Ident,
         imported_graph_ident: &'a Ident,
         data: &syn::DataStruct,
     ) -> syn::Result<Self> {
-        let mut fields = Vec::new();
-        match &data.fields {
-            syn::Fields::Named(fl) => {
-                for field in fl.named.iter() {
-                    fields.push(field.to_owned());
-                }
-            }
-            syn::Fields::Unit => {}
-            fields => {
-                return Err(syn::Error::new_spanned(
-                    fields,
-                    "Unnamed structs are not allowed.",
-                ));
-            }
-        }
+        let fields = data
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| {
+                let member = match &field.ident {
+                    Some(ident) => syn::Member::Named(ident.clone()),
+                    None => syn::Member::Unnamed(index.into()),
+                };
+                InjectedField::parse(member, field)
+            })
+            .collect::<syn::Result<_>>()?;
         Ok(Self {
             graph_ident,
             imported_graph_ident,
@@ -17,8 +17,9 @@ mod args;
 mod injected;
 mod module;
 mod route;
+mod validate;
 use crate::injected::InjectedBody;
-use crate::module::ModuleArgs;
+use crate::module::{ModuleArgs, ModuleOptions};
 use crate::route::GuardType;
 use args::Args;
 use std::str::FromStr;
@@ -100,11 +101,68 @@ pub fn test(_: TokenStream, item: TokenStream) -> TokenStream {
 }
 
 /// Derives the `Injectable` trait for dependency injection.
-#[proc_macro_derive(Injectable)]
+///
+/// The derive supports structs with named fields, tuple structs and unit structs, including
+/// generic structs whose type parameters are `Send + Sync + 'static`.
+///
+/// Fields annotated with `#[inject(name = "...")]` are injected from the provider with that name,
+/// e.g. one provided with `Module::provide_named` or `Module::provide_value_named`, rather than
+/// the unnamed provider of the field type.
+///
+/// Fields of type `Option<T>` are injected as `None` rather than failing to resolve when no
+/// provider of `T` is reachable. They may be annotated with `#[inject(optional)]` to make this
+/// explicit, which is rejected on fields of any other type.
+///
+/// Fields that are not injected, such as counters or caches, are initialized with
+/// `Default::default()` if annotated with `#[inject(default)]`, or by calling a function without
+/// arguments given by `#[inject(with = "path::to::function")]`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::graph::Value;
+/// use contraband::Injectable;
+/// use std::sync::atomic::AtomicUsize;
+/// use std::sync::Arc;
+/// use std::time::Instant;
+///
+/// #[derive(Clone, Injectable)]
+/// struct MetricsRegistry;
+///
+/// #[derive(Clone, Injectable)]
+/// struct ReportService {
+///     #[inject(name = "replica")]
+///     database_url: Value<&'static str>,
+///     metrics: Option<Arc<MetricsRegistry>>,
+///     #[inject(default)]
+///     reports_generated: Arc<AtomicUsize>,
+///     #[inject(with = "Instant::now")]
+///     started_at: Instant,
+/// }
+///
+/// trait Connection: Send + Sync {}
+///
+/// #[derive(Clone, Injectable)]
+/// struct Repository<C: Connection>(Arc<C>);
+/// ```
+#[proc_macro_derive(Injectable, attributes(inject))]
 pub fn injectable(input: TokenStream) -> TokenStream {
-    let ast = parse_macro_input!(input as DeriveInput);
+    let mut ast = parse_macro_input!(input as DeriveInput);
 
+    // Like any node of the graph, type parameters are required to be `Send + Sync + 'static`.
+    let params: Vec<_> = ast
+        .generics
+        .type_params()
+        .map(|x| x.ident.clone())
+        .collect();
+    let where_clause = ast.generics.make_where_clause();
+    for param in params {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#param: Send + Sync + 'static));
+    }
     let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
     let graph_ident = Ident::new("graph", Span::call_site());
     let context_ident = Ident::new("ctx", Span::call_site());
     let fields = match &ast.data {
@@ -119,17 +177,38 @@ pub fn injectable(input: TokenStream) -> TokenStream {
     };
     match fields {
         Ok(fi) => {
+            let dependencies = fi.dependencies();
             let expanded = quote! {
                 #[automatically_derived]
-                impl contraband::graph::Injected for #name {
+                impl #impl_generics contraband::graph::Injected for #name #ty_generics
+                #where_clause
+                {
                     type Output = Self;
                     fn resolve(
                         #graph_ident: &mut contraband::graph::Graph,
                         #context_ident: &[&contraband::graph::Graph]
-                    ) -> Self {
-                        Self {
+                    ) -> Result<Self, contraband::graph::ResolutionError> {
+                        Ok(Self {
                             #fi
-                        }
+                        })
+                    }
+
+                    fn dependencies() -> Vec<contraband::graph::Dependency> {
+                        #dependencies
+                    }
+
+                    fn hooks(instance: &std::sync::Arc<Self>) -> contraband::lifecycle::Hooks {
+                        #[allow(unused_imports)]
+                        use contraband::lifecycle::{
+                            NoHooks as _, ProbeOnApplicationBootstrap as _,
+                            ProbeOnApplicationShutdown as _, ProbeOnModuleInit as _,
+                        };
+                        let probe = contraband::lifecycle::Probe(instance.clone());
+                        contraband::lifecycle::Hooks::new(
+                            (&&probe).on_module_init_hook(),
+                            (&&probe).on_application_bootstrap_hook(),
+                            (&&probe).on_application_shutdown_hook(),
+                        )
                     }
                 }
             };
@@ -139,6 +218,114 @@ pub fn injectable(input: TokenStream) -> TokenStream {
     }
 }
 
+/// Derives the `Validate` trait for validating structs, e.g. the bodies of requests.
+///
+/// Fields are validated according to the rules of their `#[validate(...)]` attributes, see
+/// [`contraband::pipe`](../contraband/pipe/index.html). Fields annotated with `#[validate]` are
+/// validated as nested values, and their errors are prefixed with the name of the field.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::Validate;
+///
+/// fn is_isbn(isbn: &String) -> Result<(), String> {
+///     match isbn.len() {
+///         10 | 13 => Ok(()),
+///         _ => Err("must be an ISBN-10 or ISBN-13".to_string()),
+///     }
+/// }
+///
+/// #[derive(Validate)]
+/// struct Author {
+///     #[validate(length(min = 1))]
+///     name: String,
+/// }
+///
+/// #[derive(Validate)]
+/// struct NewBookInput {
+///     #[validate(length(min = 1, max = 200))]
+///     title: String,
+///     #[validate(custom = "is_isbn")]
+///     isbn: String,
+///     #[validate(range(min = 1450, max = 2100))]
+///     year: i32,
+///     #[validate]
+///     author: Author,
+/// }
+/// ```
+#[proc_macro_derive(Validate, attributes(validate))]
+pub fn derive_validate(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    match validate::derive(&ast) {
+        Ok(expanded) => TokenStream::from(expanded),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Marks a trait as an interface, which makes its trait object bindable to any implementation
+/// with `Module::bind`.
+///
+/// Syntax: `#[interface]`
+///
+/// Interfaces need to be `Send + Sync` in order to be injected as `Arc<dyn Trait>`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::{interface, module, Injectable};
+/// use std::sync::Arc;
+///
+/// #[interface]
+/// trait Mailer: Send + Sync {
+///     fn send(&self, to: &str, body: &str);
+/// }
+///
+/// #[derive(Clone, Injectable)]
+/// struct SmtpMailer;
+///
+/// impl Mailer for SmtpMailer {
+///     fn send(&self, to: &str, body: &str) {}
+/// }
+///
+/// #[derive(Clone, Injectable)]
+/// struct NewsletterService {
+///     mailer: Arc<dyn Mailer>,
+/// }
+///
+/// #[module]
+/// #[bind(dyn Mailer, SmtpMailer)]
+/// #[provider(NewsletterService)]
+/// struct NewsletterModule;
+/// ```
+#[proc_macro_attribute]
+pub fn interface(_: TokenStream, item: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(item as syn::ItemTrait);
+    let name = &input.ident;
+    let implementation = Ident::new("__ContrabandImpl", Span::call_site());
+    let (_, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut generics = input.generics.clone();
+    generics.params.push(syn::parse_quote! {
+        #implementation: #name #ty_generics + 'static
+    });
+    let (impl_generics, _, _) = generics.split_for_impl();
+    let expanded = quote! {
+        #input
+
+        #[automatically_derived]
+        impl #impl_generics contraband::module::Interface<#implementation> for dyn #name #ty_generics
+        #where_clause
+        {
+            fn upcast(
+                instance: std::sync::Arc<#implementation>,
+            ) -> std::sync::Arc<Self> {
+                instance
+            }
+        }
+    };
+    TokenStream::from(expanded)
+}
+
 /// Creates a module.
 ///
 /// Syntax: `#[module]`
@@ -177,7 +364,8 @@ pub fn injectable(input: TokenStream) -> TokenStream {
 /// # Providers
 ///
 /// In order to inject a dependency into our different structures we need to register it as a
-/// **provider**.
+/// **provider**. Providers are resolved after the providers they depend on, regardless of the
+/// order they are declared in.
 ///
 /// ## Example
 ///
@@ -231,6 +419,9 @@ pub fn injectable(input: TokenStream) -> TokenStream {
 /// In order to use a provider from another module it first needs to be exported, using the
 /// `export`-attribute. After which it can be imported in any other module using `import`.
 ///
+/// Imported modules can be exported as well, which exports everything they export, e.g. in
+/// order to bundle several modules in a facade module.
+///
 /// ## Example
 ///
 /// ```rust,no_run
@@ -279,16 +470,245 @@ pub fn injectable(input: TokenStream) -> TokenStream {
 ///         .await
 /// }
 /// ```
+///
+/// Re-exporting an imported module:
+///
+/// ```rust,no_run
+/// use contraband::config::ConfigModule;
+/// use contraband::module;
+///
+/// #[module]
+/// #[import(ConfigModule)]
+/// #[export(ConfigModule)]
+/// struct InfrastructureModule;
+/// ```
+///
+/// # Scopes
+///
+/// By default a single instance of each provider is shared by all of its dependents. The lifetime
+/// of a provider can be changed with the `scope` argument:
+///
+/// * `singleton` - a single shared instance (default)
+/// * `request` - a new instance for each HTTP request, which may depend on the
+///   `RequestContext`. Controllers depending on request-scoped providers are resolved per request.
+/// * `transient` - a new instance for each dependent
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::module;
+/// use contraband::module::RequestContext;
+/// use contraband::Injectable;
+///
+/// #[derive(Clone, Injectable)]
+/// struct CurrentUser {
+///     request: RequestContext,
+/// }
+///
+/// #[module]
+/// #[provider(CurrentUser, scope = "request")]
+/// struct UserModule;
+/// ```
+///
+/// # Profiles
+///
+/// Providers can be restricted to an active profile of the application, set with
+/// `ContrabandApp::set_profile` or the `CONTRABAND_PROFILE` environment variable, using the
+/// `profile` argument.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::module;
+/// use contraband::Injectable;
+///
+/// #[derive(Clone, Injectable)]
+/// struct InMemoryMailer;
+///
+/// #[derive(Clone, Injectable)]
+/// struct SmtpMailer;
+///
+/// #[module]
+/// #[provider(InMemoryMailer, profile = "test")]
+/// #[provider(SmtpMailer, profile = "prod")]
+/// struct MailerModule;
+/// ```
+///
+/// # Interfaces
+///
+/// Traits annotated with the [interface](attr.interface.html) macro can be bound to an
+/// implementation with the `bind` attribute, optionally for a `profile` only. The implementation
+/// is provided as well and dependents can then inject `Arc<dyn Trait>`.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::{interface, module, Injectable};
+///
+/// #[interface]
+/// trait Mailer: Send + Sync {}
+///
+/// #[derive(Clone, Injectable)]
+/// struct InMemoryMailer;
+///
+/// impl Mailer for InMemoryMailer {}
+///
+/// #[derive(Clone, Injectable)]
+/// struct SmtpMailer;
+///
+/// impl Mailer for SmtpMailer {}
+///
+/// #[module]
+/// #[bind(dyn Mailer, InMemoryMailer, profile = "test")]
+/// #[bind(dyn Mailer, SmtpMailer, profile = "prod")]
+/// struct MailerModule;
+/// ```
+///
+/// Any number of implementations can be contributed to an interface with `multi`, and are
+/// injected together as `Vec<Arc<dyn Trait>>`, including the ones contributed by imported
+/// modules.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::{interface, module, Injectable};
+/// use std::sync::Arc;
+///
+/// #[interface]
+/// trait EventListener: Send + Sync {}
+///
+/// #[derive(Clone, Injectable)]
+/// struct AuditListener;
+///
+/// impl EventListener for AuditListener {}
+///
+/// #[derive(Clone, Injectable)]
+/// struct MetricsListener;
+///
+/// impl EventListener for MetricsListener {}
+///
+/// #[derive(Clone, Injectable)]
+/// struct EventBus {
+///     listeners: Vec<Arc<dyn EventListener>>,
+/// }
+///
+/// #[module]
+/// #[bind(dyn EventListener, AuditListener, multi)]
+/// #[bind(dyn EventListener, MetricsListener, multi)]
+/// #[provider(EventBus)]
+/// struct EventModule;
+/// ```
+///
+/// # Configuration
+///
+/// Configuration sections implementing `contraband::config::ConfigSection` are registered with
+/// the `config` attribute and can then be injected as `contraband::config::Config<T>`. The
+/// `ConfigModule` which loads the configuration needs to be imported.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::config::{Config, ConfigModule, ConfigSection};
+/// use contraband::{module, Injectable};
+/// use serde::Deserialize;
+///
+/// #[derive(Deserialize)]
+/// struct CacheSettings {
+///     capacity: usize,
+/// }
+///
+/// impl ConfigSection for CacheSettings {
+///     const SECTION: &'static str = "cache";
+/// }
+///
+/// #[derive(Clone, Injectable)]
+/// struct Cache {
+///     settings: Config<CacheSettings>,
+/// }
+///
+/// #[module]
+/// #[import(ConfigModule)]
+/// #[config(CacheSettings)]
+/// #[provider(Cache)]
+/// struct CacheModule;
+/// ```
+///
+/// # Dynamic modules
+///
+/// Modules created with parameters, i.e. a `contraband::module::DynamicModule`, are imported with
+/// the `import_dynamic` attribute, which takes an expression evaluating to the module.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::graph::Value;
+/// use contraband::module;
+/// use contraband::module::{DynamicModule, Module};
+///
+/// struct GreetingModule;
+///
+/// impl GreetingModule {
+///     fn for_root(greeting: &'static str) -> DynamicModule {
+///         DynamicModule::new::<Self>(move || {
+///             Module::new()
+///                 .export_value::<Value<&'static str>>()
+///                 .provide_value(Value(greeting))
+///         })
+///     }
+/// }
+///
+/// #[module]
+/// #[import_dynamic(GreetingModule::for_root("Hello"))]
+/// struct AppModule;
+/// ```
+///
+/// # Global modules
+///
+/// The exports of modules declared with `#[module(global)]` are reachable from every module of the
+/// application without importing them. A global module still needs to be imported once, usually
+/// by the root module, and is built before all other modules.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::{module, Injectable};
+/// use std::sync::Arc;
+///
+/// #[derive(Clone, Injectable)]
+/// struct Metrics;
+///
+/// #[module(global)]
+/// #[export(Metrics)]
+/// #[provider(Metrics)]
+/// struct MetricsModule;
+///
+/// #[derive(Clone, Injectable)]
+/// struct BookService {
+///     metrics: Arc<Metrics>,
+/// }
+///
+/// #[module]
+/// #[provider(BookService)]
+/// struct BookModule;
+///
+/// #[module]
+/// #[import(MetricsModule, BookModule)]
+/// struct AppModule;
+/// ```
 #[proc_macro_attribute]
-pub fn module(_: TokenStream, item: TokenStream) -> TokenStream {
+pub fn module(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attr as ModuleOptions);
     let mut input = parse_macro_input!(item as ItemStruct);
     let name = &input.ident;
     match ModuleArgs::parse_and_strip(&mut input.attrs) {
         Ok(ModuleArgs {
             controllers,
             imports,
+            dynamic_imports,
             exports,
+            configs,
             providers,
+            bindings,
         }) => {
             let expanded = quote! {
                 #input
@@ -296,10 +716,22 @@ pub fn module(_: TokenStream, item: TokenStream) -> TokenStream {
                 #[automatically_derived]
                 impl contraband::module::ModuleFactory for #name {
                     fn get_module() -> contraband::module::Module {
-                        contraband::module::Module::new()
+                        #[allow(unused_imports)]
+                        use contraband::module::{ExportModule as _, ExportProvider as _};
+                        let module = contraband::module::Module::new()
+                            #options
                             #(.import::<#imports>())*
-                            #(.export::<#exports>())*
-                            #(.provide::<#providers>())*
+                            #(.import_dynamic(#dynamic_imports))*;
+                        #(
+                            let module = (&&contraband::module::ExportProbe::<#exports>(
+                                std::marker::PhantomData,
+                            ))
+                            .export_to(module);
+                        )*
+                        module
+                            #(.config::<#configs>())*
+                            #(#providers)*
+                            #(#bindings)*
                             #(.controller::<#controllers>())*
                     }
                 }
@@ -314,11 +746,22 @@ struct Method {
     name: Ident,
     guard_type: GuardType,
     args: Args,
+    /// The guards of `#[use_guards(...)]`, including those of the controller.
+    use_guards: Vec<syn::Path>,
+    /// The interceptors of `#[use_interceptors(...)]`, including those of the controller.
+    use_interceptors: Vec<syn::Path>,
+    /// The exception filters of `#[use_filters(...)]`, including those of the controller.
+    use_filters: Vec<syn::Path>,
+    /// The parameters annotated with `#[pipe(...)]` or `#[validate(...)]`.
+    params: Vec<validate::Param>,
     impl_item: syn::ImplItemMethod,
 }
 
 impl Method {
     fn new(impl_item: &mut syn::ImplItemMethod) -> Result<Option<Self>, syn::Error> {
+        let use_guards = args::take_paths(&mut impl_item.attrs, "use_guards")?;
+        let use_interceptors = args::take_paths(&mut impl_item.attrs, "use_interceptors")?;
+        let use_filters = args::take_paths(&mut impl_item.attrs, "use_filters")?;
         let mut guard_type = None;
         let mut args = None;
         let mut err = None;
@@ -361,6 +804,10 @@ impl Method {
                 name: format_ident!("{}_{}", "__CONTRABAND_", impl_item.sig.ident),
                 guard_type: gt,
                 args: args.unwrap_or_default(),
+                use_guards,
+                use_interceptors,
+                use_filters,
+                params: validate::take_params(&mut impl_item.sig)?,
                 impl_item: impl_item.clone(),
             })),
             None => Ok(None),
@@ -379,15 +826,55 @@ impl ToTokens for Method {
                     guards,
                     wrappers,
                 },
+            use_guards,
+            use_interceptors,
+            use_filters,
+            params,
             impl_item,
         } = self;
-        let target = &impl_item.sig.ident;
+        let (target, output, catching) = match catching_handler(name, &impl_item.sig) {
+            Some((target, output, catching)) => (target, output, Some(catching)),
+            None => {
+                let output = match &impl_item.sig.output {
+                    syn::ReturnType::Type(_, ty) => quote! { #ty },
+                    syn::ReturnType::Default => quote! { () },
+                };
+                (impl_item.sig.ident.clone(), output, None)
+            }
+        };
+        let (target, piping) = match piping_handler(name, &impl_item.sig, &target, output, params) {
+            Some((target, piping)) => (target, Some(piping)),
+            None => (target, None),
+        };
+        let use_guards = if use_guards.is_empty() {
+            None
+        } else {
+            Some(quote! {
+                .wrap(contraband::guard::UseGuards::of(provider, &[
+                    #(std::any::TypeId::of::<#use_guards>()),*
+                ]))
+            })
+        };
+        // Middleware registered first is the innermost, so guards run before interceptors, which
+        // see the responses of exception filters.
         let expanded = quote! {
+            #catching
+            #piping
+
             #[allow(non_snake_case)]
-            fn #name(&self) -> actix_web::Resource {
+            fn #name(
+                provider: &contraband::module::ControllerProvider<Self>,
+            ) -> impl actix_web::dev::HttpServiceFactory {
                 actix_web::web::resource(#path)
                     .guard(actix_web::guard::#guard_type())
                     #(.guard(actix_web::guard::fn_guard(#guards)))*
+                    .wrap(contraband::exception::UseFilters::of::<Self>(vec![
+                        #(std::any::TypeId::of::<#use_filters>()),*
+                    ]))
+                    .wrap(contraband::interceptor::UseInterceptors::of(provider, &[
+                        #(std::any::TypeId::of::<#use_interceptors>()),*
+                    ]))
+                    #use_guards
                     #(.wrap(#wrappers))*
                     .to(Self::#target)
             }
@@ -396,6 +883,111 @@ impl ToTokens for Method {
     }
 }
 
+/// Generates a handler calling the async handler of `sig` if it returns `Result<T, E>`, which
+/// wraps errors in an `Exception` for exception filters, keeping the response of errors
+/// implementing `ResponseError`. Returns the name of the generated handler together with its
+/// return type and its definition.
+fn catching_handler(
+    name: &Ident,
+    sig: &syn::Signature,
+) -> Option<(Ident, TokenStream2, TokenStream2)> {
+    let (ok_ty, err_ty) = match &sig.output {
+        syn::ReturnType::Type(_, ty) => match &**ty {
+            syn::Type::Path(path) => {
+                let segment = path.path.segments.last()?;
+                match &segment.arguments {
+                    syn::PathArguments::AngleBracketed(args)
+                        if segment.ident == "Result" && args.args.len() == 2 =>
+                    {
+                        (args.args[0].clone(), args.args[1].clone())
+                    }
+                    _ => return None,
+                }
+            }
+            _ => return None,
+        },
+        syn::ReturnType::Default => return None,
+    };
+    sig.asyncness?;
+    let target = &sig.ident;
+    let catching = format_ident!("{}_catch", name);
+    let (params, args) = handler_inputs(sig);
+    let output = quote! { Result<#ok_ty, contraband::exception::Exception> };
+    let expanded = quote! {
+        #[allow(non_snake_case)]
+        async fn #catching(#(#params),*) -> #output {
+            #[allow(unused_imports)]
+            use contraband::exception::{IntoException as _, IntoResponseException as _};
+            let probe = contraband::exception::ExceptionProbe::<#err_ty>(std::marker::PhantomData);
+            Self::#target(#(#args),*)
+                .await
+                .map_err(|error| (&&probe).exception(error))
+        }
+    };
+    Some((catching, output, expanded))
+}
+
+/// Generates a handler running the pipes and rules of `params` before calling `target`, which
+/// returns `output`, and responding with `ValidationErrors` if any parameter is invalid. Returns
+/// the name of the generated handler together with its definition.
+fn piping_handler(
+    name: &Ident,
+    sig: &syn::Signature,
+    target: &Ident,
+    output: TokenStream2,
+    params: &[validate::Param],
+) -> Option<(Ident, TokenStream2)> {
+    if params.is_empty() {
+        return None;
+    }
+    let piping = format_ident!("{}_pipe", name);
+    let (mut inputs, mut args) = handler_inputs(sig);
+    let receiver = match sig.inputs.first() {
+        Some(syn::FnArg::Receiver(_)) => 1,
+        _ => 0,
+    };
+    inputs.insert(receiver, quote! { request: actix_web::HttpRequest });
+    for param in params {
+        let arg = format_ident!("arg{}", param.index);
+        args[param.index] = quote! { #arg.expect("Parameters are valid") };
+    }
+    let pipelines = params.iter().map(validate::pipeline);
+    let expanded = quote! {
+        #[allow(non_snake_case)]
+        async fn #piping(
+            #(#inputs),*
+        ) -> Result<#output, contraband::pipe::ValidationErrors> {
+            let mut invalid = contraband::pipe::ValidationErrors::new();
+            #(#pipelines)*
+            invalid.into_result()?;
+            Ok(Self::#target(#(#args),*).await)
+        }
+    };
+    Some((piping, expanded))
+}
+
+/// Returns the parameters of a handler forwarding its arguments to the handler of `sig`, together
+/// with the forwarded arguments.
+fn handler_inputs(sig: &syn::Signature) -> (Vec<TokenStream2>, Vec<TokenStream2>) {
+    let mut params = Vec::new();
+    let mut args = Vec::new();
+    for (index, input) in sig.inputs.iter().enumerate() {
+        match input {
+            syn::FnArg::Receiver(_) => {
+                params.push(quote! { self });
+                args.push(quote! { self });
+            }
+            syn::FnArg::Typed(pat_type) => {
+                let arg = format_ident!("arg{}", index);
+                let ty = &pat_type.ty;
+                params.push(quote! { #arg: #ty });
+                args.push(quote! { #arg });
+            }
+        }
+    }
+    (params, args)
+}
+
 /// Creates a controller.
 ///
 /// Syntax: `#[controller("path")]`
@@ -430,14 +1022,55 @@ impl ToTokens for Method {
 ///
 /// Valid method attributes are:
 /// * All HTTP request methods (`get`, `post`, `put`, `delete`, `head`, `connect`, `options`, `trace`, `patch`)
+/// * `use_guards(...)`, which runs the given guards before the handler, see
+///   [`contraband::guard`](../contraband/guard/index.html)
+/// * `use_interceptors(...)`, which calls the hooks of the given interceptors around the handler,
+///   see [`contraband::interceptor`](../contraband/interceptor/index.html)
+/// * `use_filters(...)`, which catches the errors of a handler returning `Result<T, E>` with the
+///   given exception filters, see [`contraband::exception`](../contraband/exception/index.html)
+///
+/// ## Parameter attributes
+///
+/// Parameters of route handlers annotated with `#[pipe(...)]` are transformed by the given pipes,
+/// and parameters annotated with `#[validate(...)]` are validated, before the handler is called,
+/// see [`contraband::pipe`](../contraband/pipe/index.html).
+///
+/// ## Impl attributes
+///
+/// Guards and interceptors used by all routes of the controller are given by
+/// `#[use_guards(...)]` and `#[use_interceptors(...)]` below the `controller`-attribute. They are
+/// run before those of the routes. Exception filters given by `#[use_filters(...)]` are tried
+/// after those of the routes.
 #[proc_macro_attribute]
 pub fn controller(attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut input = parse_macro_input!(item as ItemImpl);
+    let controller_guards = match args::take_paths(&mut input.attrs, "use_guards") {
+        Ok(guards) => guards,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let controller_interceptors = match args::take_paths(&mut input.attrs, "use_interceptors") {
+        Ok(interceptors) => interceptors,
+        Err(err) => return err.to_compile_error().into(),
+    };
+    let controller_filters = match args::take_paths(&mut input.attrs, "use_filters") {
+        Ok(filters) => filters,
+        Err(err) => return err.to_compile_error().into(),
+    };
     let mut methods = Vec::new();
     for item in &mut input.items {
         if let syn::ImplItem::Method(ref mut item_method) = item {
             match Method::new(item_method) {
-                Ok(Some(method)) => {
+                Ok(Some(mut method)) => {
+                    method
+                        .use_guards
+                        .splice(0..0, controller_guards.iter().cloned());
+                    method
+                        .use_interceptors
+                        .splice(0..0, controller_interceptors.iter().cloned());
+                    // Filters of the route take precedence over those of the controller.
+                    method
+                        .use_filters
+                        .extend(controller_filters.iter().cloned());
                     methods.push(method);
                 }
                 Ok(None) => {}
@@ -455,6 +1088,31 @@ pub fn controller(attr: TokenStream, item: TokenStream) -> TokenStream {
             wrappers,
         }) => {
             let route_idents: Vec<&syn::Ident> = methods.iter().map(|x| &x.name).collect();
+            let all_guards =
+                args::unique_paths(methods.iter().flat_map(|method| &method.use_guards));
+            let all_interceptors =
+                args::unique_paths(methods.iter().flat_map(|method| &method.use_interceptors));
+            let all_filters =
+                args::unique_paths(methods.iter().flat_map(|method| &method.use_filters));
+            let all_pipes = args::unique_paths(
+                methods
+                    .iter()
+                    .flat_map(|method| &method.params)
+                    .flat_map(|param| param.pipes()),
+            );
+            let routes = methods.iter().map(|method| {
+                let http_method = method.guard_type.as_method();
+                let method_path = &method.args.path;
+                let handler = &method.impl_item.sig.ident;
+                quote! {
+                    contraband::describe::RouteDescription::new(
+                        #http_method,
+                        #path,
+                        #method_path,
+                        stringify!(#handler),
+                    )
+                }
+            });
             let name = &input.self_ty;
             let expanded = quote! {
                 #input
@@ -470,24 +1128,46 @@ pub fn controller(attr: TokenStream, item: TokenStream) -> TokenStream {
 
                     #[inline]
                     fn from_request(req: &actix_web::HttpRequest, _: &mut actix_web::dev::Payload) -> Self::Future {
-                        match req.app_data::<actix_web::web::Data<#name>>() {
-                            Some(st) => futures_util::future::ok(st.get_ref().clone()),
+                        match req.app_data::<actix_web::web::Data<contraband::module::ControllerProvider<#name>>>() {
+                            Some(st) => futures_util::future::ready(
+                                st.get(req).map_err(actix_web::error::ErrorInternalServerError)
+                            ),
                             None => panic!("Failed to extract data class."),
                         }
                     }
                 }
 
                 #[automatically_derived]
-                impl contraband::module::ServiceFactory for #name {
-                    fn register(&self, app: &mut actix_web::web::ServiceConfig) {
+                impl contraband::module::ControllerFactory for #name {
+                    fn register(
+                        provider: contraband::module::ControllerProvider<Self>,
+                        app: &mut actix_web::web::ServiceConfig,
+                    ) {
                         app.service(
                             actix_web::web::scope(#path)
-                            .data(self.clone())
+                            .data(provider.clone())
                             #(.guard(actix_web::guard::fn_guard(#guards)))*
                             #(.wrap(#wrappers))*
-                            #(.service(Self::#route_idents(&self)))*
+                            #(.service(Self::#route_idents(&provider)))*
                         );
                     }
+
+                    fn routes() -> Vec<contraband::describe::RouteDescription> {
+                        vec![#(#routes),*]
+                    }
+
+                    fn enhancers(
+                        graph: &mut contraband::graph::Graph,
+                        imports: &[&contraband::graph::Graph],
+                    ) -> Result<contraband::module::Enhancers, contraband::graph::ResolutionError> {
+                        #[allow(unused_mut)]
+                        let mut enhancers = contraband::module::Enhancers::default();
+                        #(enhancers.guard::<#all_guards>(graph, imports)?;)*
+                        #(enhancers.interceptor::<#all_interceptors>(graph, imports)?;)*
+                        #(enhancers.filter::<#all_filters>(graph, imports)?;)*
+                        #(enhancers.pipe::<#all_pipes>(graph, imports)?;)*
+                        Ok(enhancers)
+                    }
                 }
             };
             TokenStream::from(expanded)
@@ -1,28 +1,243 @@
-use proc_macro2::{Ident, Span};
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
 use std::collections::HashMap;
-use syn::Meta;
+use syn::parse::{Parse, ParseStream};
+use syn::{Meta, Token};
+
+pub(crate) struct Provider {
+    path: syn::Path,
+    scope: Option<Ident>,
+    profile: Option<syn::LitStr>,
+}
+
+impl Provider {
+    /// Parses the `scope = "..."` argument of a provider attribute into a variant of
+    /// `contraband::module::Scope`.
+    fn parse_scope(lit: &syn::Lit) -> syn::Result<Ident> {
+        let variant = match lit {
+            syn::Lit::Str(lit) => match lit.value().as_str() {
+                "singleton" => "Singleton",
+                "request" => "Request",
+                "transient" => "Transient",
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        lit,
+                        "Scope should be one of \"singleton\", \"request\" or \"transient\".",
+                    ))
+                }
+            },
+            lit => {
+                return Err(syn::Error::new_spanned(
+                    lit,
+                    "Scope expects literal string.",
+                ))
+            }
+        };
+        Ok(Ident::new(variant, Span::call_site()))
+    }
+
+    /// Parses the `profile = "..."` argument of a provider attribute.
+    fn parse_profile(lit: &syn::Lit) -> syn::Result<syn::LitStr> {
+        match lit {
+            syn::Lit::Str(lit) => Ok(lit.clone()),
+            lit => Err(syn::Error::new_spanned(
+                lit,
+                "Profile expects literal string.",
+            )),
+        }
+    }
+}
+
+impl ToTokens for Provider {
+    fn to_tokens(&self, stream: &mut TokenStream2) {
+        let path = &self.path;
+        let expanded = match (&self.scope, &self.profile) {
+            (scope, Some(profile)) => {
+                let scope = scope
+                    .clone()
+                    .unwrap_or_else(|| Ident::new("Singleton", Span::call_site()));
+                quote! {
+                    .provide_scoped_if::<#path>(
+                        contraband::module::Scope::#scope,
+                        |profile: &contraband::core::Profile| profile.is(#profile),
+                    )
+                }
+            }
+            (Some(scope), None) => quote! {
+                .provide_scoped::<#path>(contraband::module::Scope::#scope)
+            },
+            (None, None) => quote! {
+                .provide::<#path>()
+            },
+        };
+        stream.extend(expanded);
+    }
+}
+
+/// An interface bound to its implementation, `#[bind(dyn Interface, Implementation)]`, optionally
+/// restricted to a profile or contributed to a multi-binding with `multi`.
+pub(crate) struct Binding {
+    interface: syn::Type,
+    implementation: syn::Type,
+    profile: Option<syn::LitStr>,
+    multi: bool,
+}
+
+impl Parse for Binding {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let interface = input.parse()?;
+        input.parse::<Token![,]>()?;
+        let implementation = input.parse()?;
+        let mut profile = None;
+        let mut multi = false;
+        while input.parse::<Option<Token![,]>>()?.is_some() && !input.is_empty() {
+            let key: Ident = input.parse()?;
+            if key == "multi" {
+                multi = true;
+            } else if key == "profile" {
+                input.parse::<Token![=]>()?;
+                profile = Some(input.parse()?);
+            } else {
+                return Err(syn::Error::new_spanned(
+                    key,
+                    "Attribute bind expects an interface, an implementation, a profile and multi!",
+                ));
+            }
+        }
+        Ok(Self {
+            interface,
+            implementation,
+            profile,
+            multi,
+        })
+    }
+}
+
+impl ToTokens for Binding {
+    fn to_tokens(&self, stream: &mut TokenStream2) {
+        let interface = &self.interface;
+        let implementation = &self.implementation;
+        let expanded = match (&self.profile, self.multi) {
+            (Some(profile), false) => quote! {
+                .bind_if::<#interface, #implementation>(
+                    |profile: &contraband::core::Profile| profile.is(#profile),
+                )
+            },
+            (Some(profile), true) => quote! {
+                .bind_multi_if::<#interface, #implementation>(
+                    |profile: &contraband::core::Profile| profile.is(#profile),
+                )
+            },
+            (None, false) => quote! {
+                .bind::<#interface, #implementation>()
+            },
+            (None, true) => quote! {
+                .bind_multi::<#interface, #implementation>()
+            },
+        };
+        stream.extend(expanded);
+    }
+}
+
+/// The options of the module attribute itself, e.g. `#[module(global)]`.
+pub(crate) struct ModuleOptions {
+    pub(crate) global: bool,
+}
+
+impl Parse for ModuleOptions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut global = false;
+        if !input.is_empty() {
+            let option: Ident = input.parse()?;
+            if option != "global" {
+                return Err(syn::Error::new_spanned(
+                    option,
+                    "Attribute module expects no options or global!",
+                ));
+            }
+            global = true;
+        }
+        Ok(Self { global })
+    }
+}
+
+impl ToTokens for ModuleOptions {
+    fn to_tokens(&self, stream: &mut TokenStream2) {
+        if self.global {
+            stream.extend(quote! { .global() });
+        }
+    }
+}
 
 pub(crate) struct ModuleArgs {
     pub(crate) controllers: Vec<syn::Path>,
     pub(crate) imports: Vec<syn::Path>,
+    /// Expressions evaluating to a `contraband::module::DynamicModule`.
+    pub(crate) dynamic_imports: Vec<syn::Expr>,
     pub(crate) exports: Vec<syn::Path>,
-    pub(crate) providers: Vec<syn::Path>,
+    pub(crate) configs: Vec<syn::Path>,
+    pub(crate) providers: Vec<Provider>,
+    pub(crate) bindings: Vec<Binding>,
 }
 
 impl ModuleArgs {
     pub(crate) fn parse_and_strip(attrs: &mut std::vec::Vec<syn::Attribute>) -> syn::Result<Self> {
         let mut controllers = Vec::new();
         let mut imports = Vec::new();
+        let mut dynamic_imports = Vec::new();
         let mut exports = Vec::new();
+        let mut configs = Vec::new();
         let mut providers = Vec::new();
+        let mut bindings = Vec::new();
+        let mut provider_paths = Vec::new();
         let mut path_to_vec = HashMap::new();
         let call_site = Span::call_site();
         path_to_vec.insert(Ident::new("controller", call_site), &mut controllers);
         path_to_vec.insert(Ident::new("import", call_site), &mut imports);
         path_to_vec.insert(Ident::new("export", call_site), &mut exports);
-        path_to_vec.insert(Ident::new("provider", call_site), &mut providers);
+        path_to_vec.insert(Ident::new("config", call_site), &mut configs);
+        path_to_vec.insert(Ident::new("provider", call_site), &mut provider_paths);
         for attr in attrs.clone() {
+            if attr.path.is_ident("bind") {
+                bindings.push(attr.parse_args::<Binding>()?);
+                continue;
+            }
+            if attr.path.is_ident("import_dynamic") {
+                dynamic_imports.push(attr.parse_args::<syn::Expr>()?);
+                continue;
+            }
             match attr.parse_meta() {
+                Ok(Meta::List(nv)) if nv.path.is_ident("provider") => {
+                    let mut paths = Vec::new();
+                    let mut scope = None;
+                    let mut profile = None;
+                    for item in nv.nested {
+                        match item {
+                            syn::NestedMeta::Meta(syn::Meta::Path(path)) => paths.push(path),
+                            syn::NestedMeta::Meta(syn::Meta::NameValue(arg))
+                                if arg.path.is_ident("scope") =>
+                            {
+                                scope = Some(Provider::parse_scope(&arg.lit)?);
+                            }
+                            syn::NestedMeta::Meta(syn::Meta::NameValue(arg))
+                                if arg.path.is_ident("profile") =>
+                            {
+                                profile = Some(Provider::parse_profile(&arg.lit)?);
+                            }
+                            item => {
+                                return Err(syn::Error::new_spanned(
+                                    item,
+                                    "Attribute provider expects a path, a scope or a profile!",
+                                ));
+                            }
+                        }
+                    }
+                    providers.extend(paths.into_iter().map(|path| Provider {
+                        path,
+                        scope: scope.clone(),
+                        profile: profile.clone(),
+                    }));
+                }
                 Ok(Meta::List(nv)) => {
                     if let Some(vec) = nv.path.get_ident().and_then(|x| path_to_vec.get_mut(x)) {
                         for item in nv.nested {
@@ -55,16 +270,22 @@ impl ModuleArgs {
             }
         }
         attrs.retain(|attr| {
-            attr.path
-                .get_ident()
-                .and_then(|x| path_to_vec.get(x))
-                .is_none()
+            !attr.path.is_ident("bind")
+                && !attr.path.is_ident("import_dynamic")
+                && attr
+                    .path
+                    .get_ident()
+                    .and_then(|x| path_to_vec.get(x))
+                    .is_none()
         });
         Ok(Self {
             controllers,
             imports,
+            dynamic_imports,
             exports,
+            configs,
             providers,
+            bindings,
         })
     }
 }
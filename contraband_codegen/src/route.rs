@@ -30,6 +30,11 @@ impl GuardType {
             GuardType::Patch => "Patch",
         }
     }
+
+    /// The HTTP method of the guard, e.g. `GET`.
+    pub(crate) fn as_method(&self) -> String {
+        self.as_guard().to_uppercase()
+    }
 }
 
 impl FromStr for GuardType {
@@ -0,0 +1,253 @@
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote, ToTokens};
+use syn::{DeriveInput, NestedMeta};
+
+/// A rule of `#[validate(...)]`.
+pub(crate) enum Rule {
+    Length {
+        min: Option<syn::LitInt>,
+        max: Option<syn::LitInt>,
+    },
+    Range {
+        min: Option<syn::Lit>,
+        max: Option<syn::Lit>,
+    },
+    Custom(syn::Path),
+    /// `#[validate]` without rules, which validates a value implementing `Validate`.
+    Nested,
+}
+
+/// Parses the rules of all `#[validate(...)]` attributes of `attrs`.
+pub(crate) fn parse_rules(attrs: &[syn::Attribute]) -> syn::Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("validate")) {
+        match attr.parse_meta()? {
+            syn::Meta::Path(_) => rules.push(Rule::Nested),
+            syn::Meta::List(list) => {
+                for nested in list.nested {
+                    rules.push(parse_rule(nested)?);
+                }
+            }
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "Attribute validate expects a list of rules.",
+                ))
+            }
+        }
+    }
+    Ok(rules)
+}
+
+fn parse_rule(nested: NestedMeta) -> syn::Result<Rule> {
+    match nested {
+        NestedMeta::Meta(syn::Meta::List(list))
+            if list.path.is_ident("length") || list.path.is_ident("range") =>
+        {
+            let mut min = None;
+            let mut max = None;
+            for bound in &list.nested {
+                match bound {
+                    NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("min") => {
+                        min = Some(nv.lit.clone());
+                    }
+                    NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("max") => {
+                        max = Some(nv.lit.clone());
+                    }
+                    bound => {
+                        return Err(syn::Error::new_spanned(
+                            bound,
+                            "Expected `min = ...` or `max = ...`.",
+                        ))
+                    }
+                }
+            }
+            if list.path.is_ident("range") {
+                return Ok(Rule::Range { min, max });
+            }
+            let length = |lit: Option<syn::Lit>| match lit {
+                Some(syn::Lit::Int(lit)) => Ok(Some(lit)),
+                Some(lit) => Err(syn::Error::new_spanned(lit, "Length expects an integer.")),
+                None => Ok(None),
+            };
+            Ok(Rule::Length {
+                min: length(min)?,
+                max: length(max)?,
+            })
+        }
+        NestedMeta::Meta(syn::Meta::NameValue(nv)) if nv.path.is_ident("custom") => match nv.lit {
+            syn::Lit::Str(lit) => Ok(Rule::Custom(lit.parse()?)),
+            lit => Err(syn::Error::new_spanned(
+                lit,
+                "Custom expects the path of a function as a literal string.",
+            )),
+        },
+        nested => Err(syn::Error::new_spanned(
+            nested,
+            "Unknown rule, expected `length`, `range` or `custom`.",
+        )),
+    }
+}
+
+/// Generates code adding the errors of `value`, a reference, to `errors` for each of `rules`. The
+/// errors of nested values are prefixed with `field` if `nest` is set.
+pub(crate) fn checks(
+    rules: &[Rule],
+    value: &TokenStream2,
+    field: &str,
+    nest: bool,
+) -> TokenStream2 {
+    rules
+        .iter()
+        .map(|rule| match rule {
+            Rule::Length { min, max } => {
+                let (min, max) = (bound(min), bound(max));
+                quote! {
+                    if let Some(message) = contraband::pipe::rules::length(#value, #min, #max) {
+                        errors.add(#field, message);
+                    }
+                }
+            }
+            Rule::Range { min, max } => {
+                let (min, max) = (bound(min), bound(max));
+                quote! {
+                    if let Some(message) = contraband::pipe::rules::range(#value, #min, #max) {
+                        errors.add(#field, message);
+                    }
+                }
+            }
+            Rule::Custom(path) => quote! {
+                if let Err(message) = #path(#value) {
+                    errors.add(#field, message);
+                }
+            },
+            Rule::Nested if nest => quote! {
+                if let Err(nested) = contraband::pipe::Validate::validate(#value) {
+                    errors.nest(#field, nested);
+                }
+            },
+            Rule::Nested => quote! {
+                if let Err(nested) = contraband::pipe::Validate::validate(#value) {
+                    errors.extend(nested);
+                }
+            },
+        })
+        .collect()
+}
+
+fn bound(lit: &Option<impl ToTokens>) -> TokenStream2 {
+    match lit {
+        Some(lit) => quote! { Some(#lit) },
+        None => quote! { None },
+    }
+}
+
+/// Generates the implementation of `Validate` for a struct.
+pub(crate) fn derive(ast: &DeriveInput) -> syn::Result<TokenStream2> {
+    let fields = match &ast.data {
+        syn::Data::Struct(st) => &st.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                ast,
+                "Can only be applied to structs",
+            ))
+        }
+    };
+    let mut body = TokenStream2::new();
+    for (index, field) in fields.iter().enumerate() {
+        let rules = parse_rules(&field.attrs)?;
+        let (member, name) = match &field.ident {
+            Some(ident) => (quote! { #ident }, ident.to_string()),
+            None => {
+                let index = syn::Index::from(index);
+                (quote! { #index }, index.index.to_string())
+            }
+        };
+        body.extend(checks(&rules, &quote! { &self.#member }, &name, true));
+    }
+    let name = &ast.ident;
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    Ok(quote! {
+        #[automatically_derived]
+        impl #impl_generics contraband::pipe::Validate for #name #ty_generics #where_clause {
+            fn validate(&self) -> Result<(), contraband::pipe::ValidationErrors> {
+                #[allow(unused_mut)]
+                let mut errors = contraband::pipe::ValidationErrors::new();
+                #body
+                errors.into_result()
+            }
+        }
+    })
+}
+
+/// A parameter of a route handler annotated with `#[pipe(...)]` or `#[validate(...)]`.
+pub(crate) struct Param {
+    /// The position of the parameter, including the receiver.
+    pub(crate) index: usize,
+    /// The name of the parameter in errors.
+    name: String,
+    ty: syn::Type,
+    pipes: Vec<syn::Path>,
+    rules: Vec<Rule>,
+}
+
+impl Param {
+    pub(crate) fn pipes(&self) -> &[syn::Path] {
+        &self.pipes
+    }
+}
+
+/// Removes the `#[pipe(...)]` and `#[validate(...)]` attributes of the parameters of `sig`,
+/// returning the annotated parameters.
+pub(crate) fn take_params(sig: &mut syn::Signature) -> syn::Result<Vec<Param>> {
+    let mut params = Vec::new();
+    for (index, input) in sig.inputs.iter_mut().enumerate() {
+        if let syn::FnArg::Typed(pat_type) = input {
+            let pipes = crate::args::take_paths(&mut pat_type.attrs, "pipe")?;
+            let rules = parse_rules(&pat_type.attrs)?;
+            pat_type
+                .attrs
+                .retain(|attr| !attr.path.is_ident("validate"));
+            if pipes.is_empty() && rules.is_empty() {
+                continue;
+            }
+            let name = match &*pat_type.pat {
+                syn::Pat::Ident(pat) => pat.ident.to_string(),
+                _ => format!("arg{}", index),
+            };
+            params.push(Param {
+                index,
+                name,
+                ty: (*pat_type.ty).clone(),
+                pipes,
+                rules,
+            });
+        }
+    }
+    Ok(params)
+}
+
+/// Generates code binding the handler argument of `param` to an `Option` of its value after
+/// running its pipes and rules, adding its errors to `invalid`.
+pub(crate) fn pipeline(param: &Param) -> TokenStream2 {
+    let Param {
+        index,
+        name,
+        ty,
+        pipes,
+        rules,
+    } = param;
+    let arg = format_ident!("arg{}", index);
+    let checks = checks(rules, &quote! { &*#arg }, name, false);
+    quote! {
+        let #arg = invalid.collect((|| -> Result<#ty, contraband::pipe::ValidationErrors> {
+            #(
+                let #arg = contraband::pipe::apply::<Self, #pipes, _>(&request, #arg)?;
+            )*
+            #[allow(unused_mut)]
+            let mut errors = contraband::pipe::ValidationErrors::new();
+            #checks
+            errors.into_result().map(|()| #arg)
+        })());
+    }
+}
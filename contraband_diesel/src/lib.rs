@@ -1,9 +1,12 @@
-use contraband::config::get_prop;
+use actix_web::web;
+use contraband::config::{Config, ConfigModule, ConfigSection};
+use contraband::core::Profile;
 use contraband::graph::Value;
-use contraband::module::{Module, ModuleFactory};
+use contraband::module::{DynamicModule, Module, ModuleFactory};
 use diesel::connection::Connection;
-use diesel::r2d2::ConnectionManager;
+use diesel::r2d2::{ConnectionManager, Pool};
 use diesel_migrations::MigrationConnection;
+use serde::Deserialize;
 
 #[derive(Debug)]
 struct TestTransaction;
@@ -17,24 +20,100 @@ impl<T: Connection + 'static> diesel::r2d2::CustomizeConnection<T, diesel::r2d2:
     }
 }
 
+/// Settings of the `[diesel]` section, which can also be set through `DIESEL__CONNECTION_URL` and
+/// `DIESEL__MAX_POOL_SIZE`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct DieselSettings {
+    pub connection_url: String,
+    #[serde(default = "default_max_pool_size")]
+    pub max_pool_size: u32,
+}
+
+fn default_max_pool_size() -> u32 {
+    10
+}
+
+impl ConfigSection for DieselSettings {
+    const SECTION: &'static str = "diesel";
+}
+
+/// Provides and exports a connection pool of `T`, configured by the [`DieselSettings`].
+///
+/// The module is global, so importing it once, e.g. in the root module, makes the pool reachable
+/// from every module of the application.
+///
+/// The pool can also be configured in code rather than by the configuration with
+/// [`DieselPoolModule::for_root`].
+///
+/// When the `test` profile is active every connection runs in a test transaction which is never
+/// committed.
 pub struct DieselPoolModule<T>(std::marker::PhantomData<T>);
 
-impl<T: MigrationConnection + 'static> ModuleFactory for DieselPoolModule<T> {
+fn create_pool<T: MigrationConnection + Send + 'static>(
+    settings: &DieselSettings,
+    test_transactions: bool,
+) -> Result<Pool<ConnectionManager<T>>, String> {
+    let manager = ConnectionManager::<T>::new(settings.connection_url.as_str());
+    let mut pool_builder: r2d2::Builder<ConnectionManager<T>> =
+        r2d2::Pool::builder().max_size(settings.max_pool_size);
+    if test_transactions {
+        pool_builder = pool_builder.connection_customizer(Box::new(TestTransaction));
+    }
+    let pool = pool_builder.build(manager).map_err(|err| err.to_string())?;
+
+    let migration_conn: &T = &*pool.get().map_err(|err| err.to_string())?;
+    diesel_migrations::run_pending_migrations(migration_conn).map_err(|err| err.to_string())?;
+    Ok(pool)
+}
+
+impl<T: MigrationConnection + Send + 'static> ModuleFactory for DieselPoolModule<T> {
     fn get_module() -> Module {
-        let connspec: String = get_prop("diesel", "connection_url").expect("missing database url");
-        let manager = ConnectionManager::<T>::new(connspec);
-        let mut pool_builder: r2d2::Builder<ConnectionManager<T>> =
-            r2d2::Pool::builder().max_size(get_prop("diesel", "max_pool_size").unwrap_or(10));
-        if cfg!(test) {
-            pool_builder = pool_builder.connection_customizer(Box::new(TestTransaction));
-        }
-        let pool = Value(pool_builder.build(manager).unwrap());
-
-        let migration_conn: &T = &*pool.get().unwrap();
-        if let Err(err) = diesel_migrations::run_pending_migrations(migration_conn) {
-            panic!(err);
-        }
-
-        Module::new().export_val(&pool).provide_value(pool)
+        Module::new()
+            .global()
+            .import::<ConfigModule>()
+            .config::<DieselSettings>()
+            .export_value::<Value<Pool<ConnectionManager<T>>>>()
+            .provide_factory_async(
+                |(settings, profile): (Config<DieselSettings>, Profile)| async move {
+                    let test_transactions = profile.is("test");
+                    web::block(move || create_pool::<T>(&settings, test_transactions))
+                        .await
+                        .map(Value)
+                },
+            )
+    }
+}
+
+impl<T: MigrationConnection + Send + 'static> DieselPoolModule<T> {
+    /// Creates the module with a pool configured by `settings` instead of the `[diesel]`
+    /// configuration section.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use contraband::module;
+    /// use contraband_diesel::{DieselPoolModule, DieselSettings};
+    /// use diesel::sqlite::SqliteConnection;
+    ///
+    /// #[module]
+    /// #[import_dynamic(DieselPoolModule::<SqliteConnection>::for_root(DieselSettings {
+    ///     connection_url: "books.db".to_string(),
+    ///     max_pool_size: 4,
+    /// }))]
+    /// struct AppModule;
+    /// ```
+    pub fn for_root(settings: DieselSettings) -> DynamicModule {
+        DynamicModule::new::<Self>(move || {
+            let settings = settings.clone();
+            Module::new()
+                .global()
+                .export_value::<Value<Pool<ConnectionManager<T>>>>()
+                .provide_factory_async(|(profile,): (Profile,)| async move {
+                    let test_transactions = profile.is("test");
+                    web::block(move || create_pool::<T>(&settings, test_transactions))
+                        .await
+                        .map(Value)
+                })
+        })
     }
 }
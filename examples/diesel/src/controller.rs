@@ -1,6 +1,9 @@
-use crate::service::{BookService, InsertBook};
-use actix_web::{web, HttpResponse};
-use contraband::{controller, Injectable};
+use crate::service::{Book, BookService, InsertBook};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use async_diesel::AsyncError;
+use contraband::exception::{ExceptionFilter, Problem};
+use contraband::{controller, Injectable, Validate};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 
@@ -9,44 +12,59 @@ pub struct BookController {
     book_service: Arc<BookService>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Validate)]
 pub struct NewBookInput {
+    #[validate(length(min = 1, max = 255))]
     pub title: String,
+    #[validate(length(min = 1, max = 255))]
     pub author: String,
 }
 
+#[derive(Clone, Injectable)]
+pub struct DatabaseErrorFilter;
+
+impl ExceptionFilter for DatabaseErrorFilter {
+    type Error = AsyncError;
+
+    fn catch(&self, error: &AsyncError, _: &HttpRequest) -> HttpResponse {
+        match error {
+            AsyncError::Error(diesel::result::Error::NotFound) => {
+                Problem::new(StatusCode::NOT_FOUND).into()
+            }
+            err => Problem::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_detail(err.to_string())
+                .into(),
+        }
+    }
+}
+
 #[controller("book")]
+#[use_filters(DatabaseErrorFilter)]
 impl BookController {
     #[get]
-    async fn get_books(self) -> HttpResponse {
-        let books = self.book_service.get_books().await;
-        match books {
-            Ok(ok) => HttpResponse::Ok().json(ok),
-            Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-        }
+    async fn get_books(self) -> Result<web::Json<Vec<Book>>, AsyncError> {
+        self.book_service.get_books().await.map(web::Json)
     }
 
     #[get("/{id}")]
-    async fn get_book_by_id(self, id: web::Path<i32>) -> HttpResponse {
-        let books = self.book_service.get_book_by_id(*id).await;
-        match books {
-            Ok(ok) => HttpResponse::Ok().json(ok),
-            Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-        }
+    async fn get_book_by_id(
+        self,
+        #[validate(range(min = 1))] id: web::Path<i32>,
+    ) -> Result<web::Json<Book>, AsyncError> {
+        self.book_service.get_book_by_id(*id).await.map(web::Json)
     }
 
     #[post]
-    async fn add_book(self, input: web::Json<NewBookInput>) -> HttpResponse {
-        match self
-            .book_service
+    async fn add_book(
+        self,
+        #[validate] input: web::Json<NewBookInput>,
+    ) -> Result<HttpResponse, AsyncError> {
+        self.book_service
             .add_book(InsertBook {
                 title: input.title.clone(),
                 author: input.author.clone(),
             })
-            .await
-        {
-            Ok(()) => HttpResponse::Ok().finish(),
-            Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
-        }
+            .await?;
+        Ok(HttpResponse::Ok().finish())
     }
 }
@@ -34,7 +34,10 @@ mod tests {
 
     #[contraband::test]
     async fn add_book() {
-        let mut server = ContrabandApp::new().test_server::<AppModule>().await;
+        let mut server = ContrabandApp::new()
+            .set_profile("test")
+            .test_server::<AppModule>()
+            .await;
 
         let input = NewBookInput {
             title: "Bilbo Baggins".to_string(),
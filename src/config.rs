@@ -1,4 +1,62 @@
 //! Helpers for loading module configuration
+//!
+//! Configuration is read from `config/default.toml`, overlaid by `config/{profile}.toml` for the
+//! active [`Profile`] of the application, and finally by environment variables of the form
+//! `SECTION__PROPERTY`.
+//!
+//! Sections are deserialized into structs implementing [`ConfigSection`] and injected through
+//! [`Config`]. The [`Settings`] are provided by the [`ConfigModule`], which must be imported by
+//! any module declaring a configuration section.
+//!
+//! When the application is started all sections declared in the module tree are validated
+//! together, so that every missing or invalid key is reported at once, along with the type
+//! expected for it and the file or environment variable it was set by, instead of failing on the
+//! first one.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use contraband::config::{Config, ConfigModule, ConfigSection};
+//! use contraband::{module, Injectable};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct MailerSettings {
+//!     host: String,
+//!     port: u16,
+//! }
+//!
+//! impl ConfigSection for MailerSettings {
+//!     const SECTION: &'static str = "mailer";
+//! }
+//!
+//! #[derive(Clone, Injectable)]
+//! struct Mailer {
+//!     settings: Config<MailerSettings>,
+//! }
+//!
+//! #[module]
+//! #[import(ConfigModule)]
+//! #[config(MailerSettings)]
+//! #[provider(Mailer)]
+//! struct MailerModule;
+//! ```
+use crate::core::Profile;
+use crate::graph::{Graph, Injected, ResolutionError};
+use crate::module::{Module, ModuleFactory};
+use futures_util::future::ready;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, SeqAccess,
+    Visitor,
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use toml::value::{Table, Value};
+
 fn get_prop_str(section: &str, property: &str) -> Option<String> {
     std::env::var(format!(
         "{}__{}",
@@ -8,10 +66,1238 @@ fn get_prop_str(section: &str, property: &str) -> Option<String> {
     .ok()
 }
 
-pub fn get_prop<T: std::str::FromStr>(section: &str, property: &str) -> Option<T> {
-    let val = get_prop_str(section, property).map(|x| x.parse::<T>());
-    match val {
-        Some(Ok(val)) => Some(val),
-        _ => None,
+/// Parses the environment variable `SECTION__PROPERTY`. Returns `Ok(None)` if the variable is not
+/// set, and an error if it cannot be parsed.
+pub fn get_prop<T>(section: &str, property: &str) -> Result<Option<T>, ConfigError>
+where
+    T: std::str::FromStr,
+    T::Err: Display,
+{
+    match get_prop_str(section, property) {
+        Some(raw) => raw
+            .parse()
+            .map(Some)
+            .map_err(|err: T::Err| ConfigError::Env {
+                var: format!("{}__{}", section.to_uppercase(), property.to_uppercase()),
+                message: format!("invalid value `{}`: {}", raw, err),
+            }),
+        None => Ok(None),
+    }
+}
+
+/// Error returned when the configuration cannot be loaded or deserialized.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// A configuration file exists but could not be read.
+    Read { path: PathBuf, message: String },
+    /// A configuration file is not valid TOML.
+    Parse { path: PathBuf, message: String },
+    /// A section does not match the struct it is deserialized into.
+    Section { section: String, message: String },
+    /// An environment variable could not be parsed.
+    Env { var: String, message: String },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Read { path, message } => {
+                write!(f, "Could not read `{}`: {}", path.display(), message)
+            }
+            Self::Parse { path, message } => {
+                write!(f, "Could not parse `{}`: {}", path.display(), message)
+            }
+            Self::Section { section, message } => {
+                write!(f, "Invalid section `[{}]`: {}", section, message)
+            }
+            Self::Env { var, message } => {
+                write!(f, "Invalid environment variable `{}`: {}", var, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A section of the configuration, deserialized from the table named [`SECTION`].
+///
+/// [`SECTION`]: #associatedconstant.SECTION
+pub trait ConfigSection: DeserializeOwned + Send + Sync + 'static {
+    /// The name of the table, e.g. `diesel` for `[diesel]`.
+    const SECTION: &'static str;
+}
+
+/// Where a value of the [`Settings`] was set.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValueSource {
+    /// A configuration file, or `<string>` for settings parsed by [`Settings::from_toml`].
+    File(PathBuf),
+    /// An environment variable, e.g. `SERVER__PORT`.
+    Env(String),
+}
+
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "`{}`", path.display()),
+            Self::Env(var) => write!(f, "environment variable `{}`", var),
+        }
+    }
+}
+
+/// A key of a configuration section that is missing or does not match the type of its field.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SectionError {
+    pub section: &'static str,
+    pub type_name: &'static str,
+    pub module: Option<&'static str>,
+    /// The path of the key, e.g. `server.port`, or the name of the section if the error is not
+    /// caused by a single key.
+    pub key: String,
+    /// The type expected for the key, e.g. `u16`, if known.
+    pub expected: Option<String>,
+    /// Where the invalid value was set. `None` if the key is missing.
+    pub source: Option<ValueSource>,
+    pub message: String,
+}
+
+impl SectionError {
+    fn of<T: ConfigSection>(message: String) -> Self {
+        Self {
+            section: T::SECTION,
+            type_name: std::any::type_name::<T>(),
+            module: None,
+            key: T::SECTION.to_string(),
+            expected: None,
+            source: None,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for SectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "`{}` of `{}`", self.key, self.type_name)?;
+        if let Some(module) = self.module {
+            write!(f, " in module `{}`", module)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(expected) = &self.expected {
+            write!(f, ", expected {}", expected)?;
+        }
+        if let Some(source) = &self.source {
+            write!(f, " (from {})", source)?;
+        }
+        Ok(())
+    }
+}
+
+/// A configuration section declared by a module, which is validated together with all other
+/// declared sections before any of them is injected.
+#[derive(Clone, Copy)]
+pub(crate) struct SectionDeclaration {
+    pub(crate) module: Option<&'static str>,
+    type_name: &'static str,
+    validate: fn(&Settings) -> Vec<SectionError>,
+}
+
+impl SectionDeclaration {
+    pub(crate) fn of<T: ConfigSection>() -> Self {
+        Self {
+            module: None,
+            type_name: std::any::type_name::<T>(),
+            validate: Settings::validate::<T>,
+        }
+    }
+}
+
+/// Validates all `sections` against the [`Settings`] reachable from `graph`, reporting every
+/// missing or invalid key at once. Nothing is validated if no settings are provided, in which case
+/// injecting a section fails with a missing provider instead.
+pub(crate) fn validate_sections(
+    graph: &Graph,
+    imports: &[&Graph],
+    sections: &[SectionDeclaration],
+) -> Result<(), ResolutionError> {
+    let settings = match graph
+        .get_node::<Settings>()
+        .or_else(|| Graph::search_all(imports))
+    {
+        Some(settings) => settings,
+        None => return Ok(()),
+    };
+    let mut validated = HashSet::new();
+    let errors: Vec<_> = sections
+        .iter()
+        .filter(|declaration| validated.insert(declaration.type_name))
+        .flat_map(|declaration| {
+            (declaration.validate)(settings)
+                .into_iter()
+                .map(move |error| SectionError {
+                    module: declaration.module,
+                    ..error
+                })
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ResolutionError::InvalidConfig {
+            errors,
+            sources: settings.sources.clone(),
+        })
+    }
+}
+
+/// The merged configuration of the application.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+    root: Table,
+    /// The paths of the values set by environment variables, which are kept as strings until the
+    /// type of the field they are deserialized into is known.
+    env: HashSet<Vec<String>>,
+    /// Where the value at each path was set.
+    origins: HashMap<Vec<String>, ValueSource>,
+    sources: Vec<String>,
+}
+
+impl Settings {
+    /// Loads the configuration from the `config` directory for the profile set in
+    /// `CONTRABAND_PROFILE`, if any, and overlays it with the environment.
+    pub fn load() -> Result<Self, ConfigError> {
+        let profile = std::env::var("CONTRABAND_PROFILE").ok();
+        Self::load_from("config", profile.as_deref())
+    }
+
+    /// Loads `default.toml` and `{profile}.toml` from `dir`, ignoring files that do not exist, and
+    /// overlays the result with the environment.
+    pub fn load_from(dir: impl AsRef<Path>, profile: Option<&str>) -> Result<Self, ConfigError> {
+        let dir = dir.as_ref();
+        let mut settings = Self::default();
+        settings.merge_file(&dir.join("default.toml"))?;
+        if let Some(profile) = profile {
+            settings.merge_file(&dir.join(format!("{}.toml", profile)))?;
+        }
+        settings.merge_env(std::env::vars());
+        settings.sources.push("environment".to_string());
+        Ok(settings)
+    }
+
+    /// Parses settings from a TOML string, without reading any files or the environment.
+    pub fn from_toml(source: &str) -> Result<Self, ConfigError> {
+        let path = PathBuf::from("<string>");
+        let root = toml::from_str(source).map_err(|err| ConfigError::Parse {
+            path: path.clone(),
+            message: err.to_string(),
+        })?;
+        let mut settings = Self::default();
+        settings.record_origins(&mut Vec::new(), &root, &ValueSource::File(path));
+        settings.root = root;
+        Ok(settings)
+    }
+
+    /// Returns where the settings were loaded from, in the order they were merged.
+    pub fn sources(&self) -> &[String] {
+        &self.sources
+    }
+
+    /// Deserializes the table `name` into `T`. A missing table is treated as an empty table, so
+    /// sections where all fields have defaults need not be present.
+    pub fn section<T: DeserializeOwned>(&self, name: &str) -> Result<T, ConfigError> {
+        let table = self
+            .root
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| Value::Table(Table::new()));
+        T::deserialize(SettingsDeserializer {
+            value: table,
+            path: vec![name.to_string()],
+            env: &self.env,
+        })
+        .map_err(|err| ConfigError::Section {
+            section: name.to_string(),
+            message: err.to_string(),
+        })
+    }
+
+    /// Validates the section of `T`, returning every missing or invalid key instead of failing on
+    /// the first one like [`section`](#method.section).
+    pub(crate) fn validate<T: ConfigSection>(&self) -> Vec<SectionError> {
+        let validation = Validation {
+            settings: self,
+            section: T::SECTION,
+            type_name: std::any::type_name::<T>(),
+            errors: RefCell::default(),
+            missing: RefCell::default(),
+        };
+        // Fields without a default are only reported missing by the `Deserialize` implementation
+        // once all present keys have been deserialized, so the section is validated again with a
+        // placeholder for each until all keys have been reported.
+        loop {
+            validation.errors.borrow_mut().clear();
+            let value = self
+                .root
+                .get(T::SECTION)
+                .cloned()
+                .unwrap_or_else(|| Value::Table(Table::new()));
+            let result = T::deserialize(Validator {
+                value: Some(value),
+                path: vec![T::SECTION.to_string()],
+                validation: &validation,
+                silent: false,
+            });
+            let mut errors = validation.errors.replace(Vec::new());
+            match result {
+                Ok(_) => return errors,
+                Err(ValidationError::Missing) => continue,
+                Err(err) => {
+                    errors.push(SectionError::of::<T>(err.to_string()));
+                    return errors;
+                }
+            }
+        }
+    }
+
+    fn merge_file(&mut self, path: &Path) -> Result<(), ConfigError> {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => {
+                return Err(ConfigError::Read {
+                    path: path.to_path_buf(),
+                    message: err.to_string(),
+                })
+            }
+        };
+        let table = toml::from_str(&source).map_err(|err| ConfigError::Parse {
+            path: path.to_path_buf(),
+            message: err.to_string(),
+        })?;
+        self.record_origins(
+            &mut Vec::new(),
+            &table,
+            &ValueSource::File(path.to_path_buf()),
+        );
+        merge(&mut self.root, table);
+        self.sources.push(path.display().to_string());
+        Ok(())
+    }
+
+    /// Records `source` as the origin of all values of `table`, which is found at `path`.
+    fn record_origins(&mut self, path: &mut Vec<String>, table: &Table, source: &ValueSource) {
+        for (key, value) in table {
+            path.push(key.clone());
+            self.origins.insert(path.clone(), source.clone());
+            if let Value::Table(table) = value {
+                self.record_origins(path, table, source);
+            }
+            path.pop();
+        }
+    }
+
+    /// Overlays variables of the form `SECTION__PROPERTY`, where nested tables are separated by
+    /// further double underscores. Values are kept as strings and parsed once they are
+    /// deserialized into a field, e.g. as a number for a `u16` field, so that `DB__PASSWORD=1234`
+    /// still sets a `String` field.
+    fn merge_env(&mut self, vars: impl Iterator<Item = (String, String)>) {
+        for (key, raw) in vars {
+            let path: Vec<_> = key.split("__").map(|x| x.to_lowercase()).collect();
+            if path.len() < 2 || path.iter().any(|x| x.is_empty()) {
+                continue;
+            }
+            let value = Value::String(raw);
+            self.env.insert(path.clone());
+            self.origins.insert(path.clone(), ValueSource::Env(key));
+            let (property, sections) = path.split_last().unwrap();
+            let mut table = &mut self.root;
+            for section in sections {
+                let entry = table
+                    .entry(section.clone())
+                    .or_insert_with(|| Value::Table(Table::new()));
+                if !entry.is_table() {
+                    *entry = Value::Table(Table::new());
+                }
+                table = entry.as_table_mut().unwrap();
+            }
+            table.insert(property.clone(), value);
+        }
+    }
+}
+
+/// Recursively merges `overlay` into `base`, replacing all values except tables.
+fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(overlay)) => merge(base, overlay),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Deserializes a value of the settings at `path`, parsing the strings set by environment
+/// variables according to the type they are deserialized into.
+struct SettingsDeserializer<'a> {
+    value: Value,
+    path: Vec<String>,
+    env: &'a HashSet<Vec<String>>,
+}
+
+impl<'a> SettingsDeserializer<'a> {
+    /// Returns the string of an environment variable, or `self` for any other value.
+    fn env_value(self) -> Result<(String, Vec<String>), Self> {
+        match self.value {
+            Value::String(raw) if self.env.contains(&self.path) => Ok((raw, self.path)),
+            value => Err(Self { value, ..self }),
+        }
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident $visit:ident $ty:ty),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                match self.env_value() {
+                    Ok((raw, path)) => match raw.parse::<$ty>() {
+                        Ok(value) => visitor.$visit(value),
+                        Err(err) => Err(de::Error::custom(format!(
+                            "invalid value `{}` for `{}`: {}",
+                            raw,
+                            path.join("."),
+                            err
+                        ))),
+                    },
+                    Err(this) => this.deserialize_any(visitor),
+                }
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> Deserializer<'de> for SettingsDeserializer<'a> {
+    type Error = toml::de::Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.env_value() {
+            // Without a type to parse into, strings of the environment are parsed as TOML.
+            Ok((raw, _)) => toml::from_str::<Table>(&format!("value = {}", raw))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or(Value::String(raw))
+                .deserialize_any(visitor),
+            Err(Self {
+                value: Value::Table(table),
+                path,
+                env,
+            }) => visitor.visit_map(TableAccess {
+                entries: table.into_iter(),
+                next: None,
+                path,
+                env,
+            }),
+            Err(this) => this.value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.value.deserialize_enum(name, variants, visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.env_value() {
+            Ok((raw, _)) => visitor.visit_string(raw),
+            Err(this) => this.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    deserialize_parsed! {
+        deserialize_bool visit_bool bool,
+        deserialize_i8 visit_i8 i8,
+        deserialize_i16 visit_i16 i16,
+        deserialize_i32 visit_i32 i32,
+        deserialize_i64 visit_i64 i64,
+        deserialize_u8 visit_u8 u8,
+        deserialize_u16 visit_u16 u16,
+        deserialize_u32 visit_u32 u32,
+        deserialize_u64 visit_u64 u64,
+        deserialize_f32 visit_f32 f32,
+        deserialize_f64 visit_f64 f64,
+        deserialize_char visit_char char
+    }
+
+    serde::forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+/// The entries of a table of the settings.
+struct TableAccess<'a> {
+    entries: <Table as IntoIterator>::IntoIter,
+    next: Option<Value>,
+    path: Vec<String>,
+    env: &'a HashSet<Vec<String>>,
+}
+
+impl<'de, 'a> MapAccess<'de> for TableAccess<'a> {
+    type Error = toml::de::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.next = Some(value);
+                self.path.push(key.clone());
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self.next.take().expect("values are taken after their keys");
+        let path = self.path.clone();
+        self.path.pop();
+        seed.deserialize(SettingsDeserializer {
+            value,
+            path,
+            env: self.env,
+        })
+    }
+}
+
+/// The state of validating a section with a [`Validator`].
+struct Validation<'a> {
+    settings: &'a Settings,
+    section: &'static str,
+    type_name: &'static str,
+    errors: RefCell<Vec<SectionError>>,
+    /// The paths of the fields reported missing, which are deserialized from placeholders.
+    missing: RefCell<HashSet<Vec<String>>>,
+}
+
+#[derive(Debug)]
+enum ValidationError {
+    /// A field without a default is missing.
+    MissingField(&'static str),
+    /// A missing field has been recorded and the section is to be validated again.
+    Missing,
+    Custom(String),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingField(field) => write!(f, "missing field `{}`", field),
+            Self::Missing => write!(f, "missing field"),
+            Self::Custom(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl de::Error for ValidationError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self::Custom(msg.to_string())
+    }
+
+    fn missing_field(field: &'static str) -> Self {
+        Self::MissingField(field)
+    }
+}
+
+/// Deserializes a value of the settings like [`SettingsDeserializer`], but records each key that
+/// is missing or does not match the type it is deserialized into, and deserializes a placeholder
+/// in its place to go on with the remaining keys.
+struct Validator<'a> {
+    /// The value at `path`, or `None` if it is missing.
+    value: Option<Value>,
+    path: Vec<String>,
+    validation: &'a Validation<'a>,
+    /// Whether the value is part of a placeholder, whose keys are not reported.
+    silent: bool,
+}
+
+impl<'a> Validator<'a> {
+    fn record(&self, expected: Option<String>, message: String) {
+        if self.silent {
+            return;
+        }
+        // Elements of arrays are set by the source of the array.
+        let origins = &self.validation.settings.origins;
+        let source = match self.value {
+            Some(_) => (1..=self.path.len())
+                .rev()
+                .find_map(|len| origins.get(&self.path[..len]))
+                .cloned(),
+            None => None,
+        };
+        self.validation.errors.borrow_mut().push(SectionError {
+            section: self.validation.section,
+            type_name: self.validation.type_name,
+            module: None,
+            key: self.path.join("."),
+            expected,
+            source,
+            message,
+        });
+    }
+
+    /// Returns the string of an environment variable, if the value was set by one.
+    fn env_value(&self) -> Option<&str> {
+        match &self.value {
+            Some(Value::String(raw)) if self.validation.settings.env.contains(&self.path) => {
+                Some(raw)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether the value can be deserialized as `expected`, checking values of the
+    /// environment with `parse` and any other value with `check`, and records it otherwise.
+    fn check(
+        &self,
+        expected: &str,
+        check: impl FnOnce(&Value) -> Result<(), String>,
+        parse: impl FnOnce(&str) -> Result<(), String>,
+    ) -> bool {
+        let result = match (self.env_value(), &self.value) {
+            (Some(raw), _) => parse(raw).map_err(|err| format!("invalid value `{}`: {}", raw, err)),
+            (None, Some(value)) => check(value),
+            (None, None) => Err("missing".to_string()),
+        };
+        match result {
+            Ok(()) => true,
+            Err(message) => {
+                self.record(Some(expected.to_string()), message);
+                false
+            }
+        }
+    }
+
+    fn deserializer(self) -> SettingsDeserializer<'a> {
+        SettingsDeserializer {
+            value: self.value.expect("only present values are deserialized"),
+            path: self.path,
+            env: &self.validation.settings.env,
+        }
+    }
+
+    /// Visits the table of the value, or an empty table with a placeholder for each of `fields`
+    /// if the value is missing or not a table.
+    fn visit_table<'de, V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, ValidationError> {
+        let (table, silent) = match &self.value {
+            Some(Value::Table(table)) => (table.clone(), self.silent),
+            value => {
+                let message = match value {
+                    Some(value) => invalid_value(value),
+                    None => "missing".to_string(),
+                };
+                self.record(Some("table".to_string()), message);
+                (Table::new(), true)
+            }
+        };
+        let mut entries: Vec<_> = table
+            .into_iter()
+            .map(|(key, value)| (key, Some(value)))
+            .collect();
+        {
+            let missing = self.validation.missing.borrow();
+            for field in fields {
+                let mut path = self.path.clone();
+                path.push(field.to_string());
+                let absent = !entries.iter().any(|(key, _)| key == field);
+                if absent && (silent || missing.contains(&path)) {
+                    entries.push((field.to_string(), None));
+                }
+            }
+        }
+        let result = visitor.visit_map(ValidatorTableAccess {
+            entries: entries.into_iter(),
+            next: None,
+            path: self.path.clone(),
+            validation: self.validation,
+            silent,
+        });
+        match result {
+            Err(ValidationError::MissingField(field)) => {
+                let mut path = self.path;
+                path.push(field.to_string());
+                if self.validation.missing.borrow_mut().insert(path) {
+                    Err(ValidationError::Missing)
+                } else {
+                    Err(ValidationError::MissingField(field))
+                }
+            }
+            result => result,
+        }
+    }
+}
+
+fn invalid_value(value: &Value) -> String {
+    format!("invalid {} {}", value.type_str(), value)
+}
+
+/// Checks that a value is of the TOML type `$variant`, or that a string of the environment parses
+/// as `$ty`, and deserializes a placeholder otherwise.
+macro_rules! validate_parsed {
+    ($($method:ident $visit:ident $ty:ty = $placeholder:expr, $check:expr),*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+                let valid = self.check(
+                    stringify!($ty),
+                    $check,
+                    |raw| raw.parse::<$ty>().map(|_| ()).map_err(|err| err.to_string()),
+                );
+                if valid {
+                    self.deserializer()
+                        .$method(visitor)
+                        .map_err(|err| ValidationError::Custom(err.to_string()))
+                } else {
+                    visitor.$visit($placeholder)
+                }
+            }
+        )*
+    };
+}
+
+/// Checks that a TOML integer fits into `$ty`.
+macro_rules! integer {
+    ($ty:ty) => {
+        |value: &Value| match value {
+            Value::Integer(i) => <$ty as std::convert::TryFrom<i64>>::try_from(*i)
+                .map(|_| ())
+                .map_err(|err| format!("invalid value {}: {}", i, err)),
+            value => Err(invalid_value(value)),
+        }
+    };
+}
+
+fn float(value: &Value) -> Result<(), String> {
+    match value {
+        Value::Float(_) | Value::Integer(_) => Ok(()),
+        value => Err(invalid_value(value)),
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for Validator<'a> {
+    type Error = ValidationError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Some(Value::Table(_)) => self.visit_table(&[], visitor),
+            Some(Value::Array(_)) => self.deserialize_seq(visitor),
+            Some(_) => self
+                .deserializer()
+                .deserialize_any(visitor)
+                .map_err(|err| ValidationError::Custom(err.to_string())),
+            None => {
+                self.record(None, "missing".to_string());
+                visitor.visit_unit()
+            }
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        match self.value {
+            Some(_) => visitor.visit_some(self),
+            None => visitor.visit_none(),
+        }
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        let expected = format!(
+            "one of {}",
+            variants
+                .iter()
+                .map(|variant| format!("`{}`", variant))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let valid = self.check(
+            &expected,
+            |value| match value {
+                Value::String(variant) if !variants.contains(&variant.as_str()) => {
+                    Err(format!("unknown variant `{}`", variant))
+                }
+                Value::String(_) | Value::Table(_) => Ok(()),
+                value => Err(invalid_value(value)),
+            },
+            |raw| {
+                if variants.contains(&raw) {
+                    Ok(())
+                } else {
+                    Err("unknown variant".to_string())
+                }
+            },
+        );
+        match (valid, variants.first()) {
+            (true, _) => self
+                .deserializer()
+                .deserialize_enum(name, variants, visitor)
+                .map_err(|err| ValidationError::Custom(err.to_string())),
+            (false, Some(variant)) => visitor.visit_enum(variant.into_deserializer()),
+            (false, None) => Err(ValidationError::Custom(format!(
+                "no variants of `{}`",
+                name
+            ))),
+        }
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        // Like other values without a type to parse into, arrays of the environment are parsed as
+        // TOML.
+        let values = match (self.env_value(), &self.value) {
+            (Some(raw), _) => match toml::from_str::<Table>(&format!("value = {}", raw)) {
+                Ok(mut table) => match table.remove("value") {
+                    Some(Value::Array(values)) => Ok(values),
+                    _ => Err(format!("invalid value `{}`", raw)),
+                },
+                Err(_) => Err(format!("invalid value `{}`", raw)),
+            },
+            (None, Some(Value::Array(values))) => Ok(values.clone()),
+            (None, Some(value)) => Err(invalid_value(value)),
+            (None, None) => Err("missing".to_string()),
+        };
+        let values = values.unwrap_or_else(|message| {
+            self.record(Some("array".to_string()), message);
+            Vec::new()
+        });
+        visitor.visit_seq(ValidatorSeqAccess {
+            values: values.into_iter().enumerate(),
+            path: self.path,
+            validation: self.validation,
+            silent: self.silent,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.visit_table(&[], visitor)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.visit_table(fields, visitor)
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        let valid = self.check(
+            "string",
+            |value| match value {
+                Value::String(_) => Ok(()),
+                value => Err(invalid_value(value)),
+            },
+            |_| Ok(()),
+        );
+        if valid {
+            self.deserializer()
+                .deserialize_str(visitor)
+                .map_err(|err| ValidationError::Custom(err.to_string()))
+        } else {
+            visitor.visit_str("")
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_str(visitor)
+    }
+
+    validate_parsed! {
+        deserialize_bool visit_bool bool = false, |value: &Value| match value {
+            Value::Boolean(_) => Ok(()),
+            value => Err(invalid_value(value)),
+        },
+        deserialize_i8 visit_i8 i8 = 0, integer!(i8),
+        deserialize_i16 visit_i16 i16 = 0, integer!(i16),
+        deserialize_i32 visit_i32 i32 = 0, integer!(i32),
+        deserialize_i64 visit_i64 i64 = 0, integer!(i64),
+        deserialize_u8 visit_u8 u8 = 0, integer!(u8),
+        deserialize_u16 visit_u16 u16 = 0, integer!(u16),
+        deserialize_u32 visit_u32 u32 = 0, integer!(u32),
+        deserialize_u64 visit_u64 u64 = 0, integer!(u64),
+        deserialize_f32 visit_f32 f32 = 0.0, float,
+        deserialize_f64 visit_f64 f64 = 0.0, float,
+        deserialize_char visit_char char = '\0', |value: &Value| match value {
+            Value::String(s) if s.chars().count() == 1 => Ok(()),
+            value => Err(invalid_value(value)),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct identifier ignored_any
+    }
+}
+
+/// The entries of a table validated by a [`Validator`], where missing fields have no value.
+struct ValidatorTableAccess<'a> {
+    entries: std::vec::IntoIter<(String, Option<Value>)>,
+    next: Option<Option<Value>>,
+    path: Vec<String>,
+    validation: &'a Validation<'a>,
+    silent: bool,
+}
+
+impl<'de, 'a> MapAccess<'de> for ValidatorTableAccess<'a> {
+    type Error = ValidationError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.next = Some(value);
+                self.path.push(key.clone());
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, Self::Error> {
+        let value = self.next.take().expect("values are taken after their keys");
+        let path = self.path.clone();
+        self.path.pop();
+        seed.deserialize(Validator {
+            value,
+            path,
+            validation: self.validation,
+            silent: self.silent,
+        })
+    }
+}
+
+/// The elements of an array validated by a [`Validator`].
+struct ValidatorSeqAccess<'a> {
+    values: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+    path: Vec<String>,
+    validation: &'a Validation<'a>,
+    silent: bool,
+}
+
+impl<'de, 'a> SeqAccess<'de> for ValidatorSeqAccess<'a> {
+    type Error = ValidationError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.values.next() {
+            Some((index, value)) => {
+                let mut path = self.path.clone();
+                path.push(index.to_string());
+                seed.deserialize(Validator {
+                    value: Some(value),
+                    path,
+                    validation: self.validation,
+                    silent: self.silent,
+                })
+                .map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+impl Injected for Settings {
+    type Output = Self;
+    fn resolve(_: &mut Graph, _: &[&Graph]) -> Result<Self, ResolutionError> {
+        Err(ResolutionError::missing::<Self>())
+    }
+}
+
+/// An injectable configuration section.
+///
+/// Sections are registered in a module with [`Module::config`], or the `config` attribute of the
+/// [module](../attr.module.html) macro.
+pub struct Config<T>(Arc<T>);
+
+impl<T> Config<T> {
+    pub fn new(section: T) -> Self {
+        Self(Arc::new(section))
+    }
+}
+
+impl<T> Deref for Config<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T> Clone for Config<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Config<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Config").field(&self.0).finish()
+    }
+}
+
+impl<T: ConfigSection> Injected for Config<T> {
+    type Output = Self;
+    fn resolve(graph: &mut Graph, imported: &[&Graph]) -> Result<Self, ResolutionError> {
+        let settings = graph.inject::<Settings>(imported)?.ok_or_else(|| {
+            ResolutionError::MissingProvider {
+                type_name: std::any::type_name::<Settings>(),
+                name: None,
+                required_by: Some(std::any::type_name::<Self>()),
+                field: None,
+                module: None,
+            }
+        })?;
+        settings.section(T::SECTION).map(Self::new).map_err(|err| {
+            let mut errors = settings.validate::<T>();
+            if errors.is_empty() {
+                errors.push(SectionError::of::<T>(err.to_string()));
+            }
+            ResolutionError::InvalidConfig {
+                errors,
+                sources: settings.sources.clone(),
+            }
+        })
+    }
+}
+
+/// Loads the [`Settings`] of the application from the `config` directory for the active
+/// [`Profile`] and exports them.
+pub struct ConfigModule;
+
+impl ModuleFactory for ConfigModule {
+    fn get_module() -> Module {
+        Module::new()
+            .export_value::<Settings>()
+            .provide_factory_async(|(profile,): (Profile,)| {
+                ready(Settings::load_from("config", profile.name()))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct ServerSettings {
+        host: String,
+        #[serde(default)]
+        port: u16,
+    }
+
+    #[test]
+    fn test_section() {
+        let settings = Settings::from_toml("[server]\nhost = \"localhost\"\nport = 80").unwrap();
+        assert_eq!(
+            settings.section::<ServerSettings>("server").unwrap(),
+            ServerSettings {
+                host: "localhost".to_string(),
+                port: 80,
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_section() {
+        let settings = Settings::from_toml("[server]\nport = 80").unwrap();
+        assert_eq!(
+            settings.section::<ServerSettings>("server"),
+            Err(ConfigError::Section {
+                section: "server".to_string(),
+                message: "missing field `host`".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_profile_and_env_overlay() {
+        let dir = std::env::temp_dir().join("contraband_test_profile_and_env_overlay");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("default.toml"),
+            "[server]\nhost = \"localhost\"\nport = 80",
+        )
+        .unwrap();
+        std::fs::write(dir.join("prod.toml"), "[server]\nhost = \"example.com\"").unwrap();
+
+        let mut settings = Settings::load_from(&dir, Some("prod")).unwrap();
+        settings.merge_env(vec![("SERVER__PORT".to_string(), "443".to_string())].into_iter());
+        assert_eq!(
+            settings.section::<ServerSettings>("server").unwrap(),
+            ServerSettings {
+                host: "example.com".to_string(),
+                port: 443,
+            }
+        );
+    }
+
+    #[test]
+    fn test_env_values_are_deserialized_by_field_type() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct DatabaseSettings {
+            password: String,
+            version: String,
+            pool_size: Option<u32>,
+            ssl: bool,
+        }
+
+        let mut settings = Settings::default();
+        settings.merge_env(
+            vec![
+                ("DB__PASSWORD", "1234"),
+                ("DB__VERSION", "1.0"),
+                ("DB__POOL_SIZE", "8"),
+                ("DB__SSL", "true"),
+            ]
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string())),
+        );
+        assert_eq!(
+            settings.section::<DatabaseSettings>("db").unwrap(),
+            DatabaseSettings {
+                password: "1234".to_string(),
+                version: "1.0".to_string(),
+                pool_size: Some(8),
+                ssl: true,
+            }
+        );
+
+        settings.merge_env(vec![("DB__SSL".to_string(), "sometimes".to_string())].into_iter());
+        assert!(settings.section::<DatabaseSettings>("db").is_err());
+    }
+
+    #[test]
+    fn test_validation_reports_every_key() {
+        #[derive(Debug, Deserialize)]
+        #[allow(dead_code)]
+        struct MailerSettings {
+            host: String,
+            port: u16,
+            user: String,
+            tls: bool,
+            #[serde(default)]
+            retries: u32,
+            timeout: Option<u64>,
+        }
+
+        impl ConfigSection for MailerSettings {
+            const SECTION: &'static str = "mailer";
+        }
+
+        let mut settings = Settings::from_toml("[mailer]\nport = \"smtp\"\ntimeout = 5").unwrap();
+        settings.merge_env(vec![("MAILER__TLS".to_string(), "sometimes".to_string())].into_iter());
+        let error =
+            |key: &str, expected: &str, source: Option<ValueSource>, message: &str| SectionError {
+                section: "mailer",
+                type_name: std::any::type_name::<MailerSettings>(),
+                module: None,
+                key: key.to_string(),
+                expected: Some(expected.to_string()),
+                source,
+                message: message.to_string(),
+            };
+        assert_eq!(
+            settings.validate::<MailerSettings>(),
+            vec![
+                error(
+                    "mailer.port",
+                    "u16",
+                    Some(ValueSource::File(PathBuf::from("<string>"))),
+                    "invalid string \"smtp\"",
+                ),
+                error(
+                    "mailer.tls",
+                    "bool",
+                    Some(ValueSource::Env("MAILER__TLS".to_string())),
+                    "invalid value `sometimes`: provided string was not `true` or `false`",
+                ),
+                error("mailer.host", "string", None, "missing"),
+                error("mailer.user", "string", None, "missing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_prop_reports_invalid_values() {
+        std::env::set_var("GET_PROP_TEST__PORT", "http");
+        assert_eq!(
+            get_prop::<u16>("get_prop_test", "port"),
+            Err(ConfigError::Env {
+                var: "GET_PROP_TEST__PORT".to_string(),
+                message: "invalid value `http`: invalid digit found in string".to_string(),
+            })
+        );
+        assert_eq!(get_prop::<u16>("get_prop_test", "host"), Ok(None));
     }
 }
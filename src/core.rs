@@ -3,19 +3,127 @@ extern crate rustls;
 
 use actix_tls::rustls::ServerConfig as RustlsServerConfig;
 
-use super::graph::Graph;
+use super::graph::{Graph, Injected, ResolutionError as GraphResolutionError};
+use crate::describe::ModuleDescription;
+use crate::exception::{self, Catch, ExceptionFilter, FilterFactory, UseFilters};
+use crate::interceptor::{self, Interceptor};
+use crate::lifecycle::Lifecycle;
 use crate::log::{ConsoleLoggingProvider, LogLevel, Logger, LoggingProvider};
-use crate::module::{Context, ModuleFactory, ResolvedModule};
-use actix_web::{dev::Service, App, HttpServer};
+use crate::module::{
+    build_module_ref, provide_global_module, Context, DeclaredModule, ModuleFactory, ModuleRef,
+    Overrides, RequestContext, RequestContextProvider, ResolutionError, ResolvedModule,
+};
+use crate::testing::TestServer;
+use actix_web::dev::{Server, Service};
+use actix_web::{App, HttpServer};
 use listenfd::ListenFd;
-use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
+
+/// Handle for gracefully stopping the application.
+///
+/// The handle can be injected into any provider or controller, e.g. in order to stop the
+/// application from an admin endpoint. Stopping the application stops accepting new connections
+/// and waits for in-flight requests to finish, or the shutdown timeout to pass, before the
+/// application shutdown hooks are called.
+#[derive(Clone, Default)]
+pub struct ShutdownHandle {
+    state: Arc<Mutex<ShutdownState>>,
+}
+
+#[derive(Default)]
+struct ShutdownState {
+    server: Option<Server>,
+    requested: bool,
+}
+
+impl ShutdownHandle {
+    /// Requests a graceful shutdown of the application. If the server has not started yet it is
+    /// stopped as soon as it starts.
+    pub fn shutdown(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.requested = true;
+        if let Some(server) = &state.server {
+            drop(server.stop(true));
+        }
+    }
+
+    fn attach(&self, server: Server) {
+        let mut state = self.state.lock().unwrap();
+        if state.requested {
+            // Defer stopping until the server is being awaited, otherwise the stop is handled
+            // before the server registers for its completion.
+            let server = server.clone();
+            actix_rt::spawn(async move { server.stop(true).await });
+        }
+        state.server = Some(server);
+    }
+}
+
+impl Injected for ShutdownHandle {
+    type Output = Self;
+    fn resolve(_: &mut Graph, _: &[&Graph]) -> Result<Self, GraphResolutionError> {
+        Err(GraphResolutionError::missing::<Self>())
+    }
+}
+
+/// The active profile of the application, e.g. `dev`, `test` or `prod`.
+///
+/// The profile is set with [`ContrabandApp::set_profile`] and defaults to the
+/// `CONTRABAND_PROFILE` environment variable. It selects the providers registered with
+/// [`Module::provide_if`](crate::module::Module::provide_if) and the configuration files loaded
+/// by the [`ConfigModule`](crate::config::ConfigModule), and can be injected into any provider.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Profile(Option<String>);
+
+impl Profile {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(Some(name.into()))
+    }
+
+    fn from_env() -> Self {
+        Self(std::env::var("CONTRABAND_PROFILE").ok())
+    }
+
+    /// Returns the name of the profile, or `None` if no profile is active.
+    pub fn name(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+
+    /// Whether `name` is the active profile.
+    pub fn is(&self, name: &str) -> bool {
+        self.name() == Some(name)
+    }
+}
+
+impl Injected for Profile {
+    type Output = Self;
+    fn resolve(_: &mut Graph, _: &[&Graph]) -> Result<Self, GraphResolutionError> {
+        Err(GraphResolutionError::missing::<Self>())
+    }
+}
 
 struct AppConfig {
     pub port: u16,
     pub logging_provider: Arc<dyn LoggingProvider>,
     pub log_level: LogLevel,
     pub tls_config: Option<RustlsServerConfig>,
+    pub shutdown_timeout: u64,
+    pub handle_signals: bool,
+    pub shutdown_handle: ShutdownHandle,
+    pub profile: Profile,
+    pub interceptors: Vec<InterceptorFactory>,
+    pub filters: Vec<FilterFactory>,
+}
+
+type InterceptorFactory =
+    fn(&mut Graph, &[&Graph]) -> Result<Arc<dyn Interceptor>, GraphResolutionError>;
+
+/// A resolved module tree together with the lifecycle hooks of its providers and the global
+/// exception filters.
+struct Application {
+    module: Arc<ResolvedModule>,
+    lifecycle: Lifecycle,
+    filters: Vec<Arc<dyn Catch>>,
 }
 
 impl AppConfig {
@@ -25,6 +133,12 @@ impl AppConfig {
             logging_provider: Arc::new(ConsoleLoggingProvider),
             log_level: LogLevel::Info,
             tls_config: None,
+            shutdown_timeout: 30,
+            handle_signals: true,
+            shutdown_handle: ShutdownHandle::default(),
+            profile: Profile::from_env(),
+            interceptors: Vec::new(),
+            filters: Vec::new(),
         }
     }
 
@@ -34,10 +148,10 @@ impl AppConfig {
             self.logging_provider.clone(),
             self.log_level,
         )));
-        Context {
-            global_providers: graph,
-            modules: HashMap::new(),
-        }
+        graph.provide(Arc::new(self.shutdown_handle.clone()));
+        graph.provide(Arc::new(self.profile.clone()));
+        graph.provide_scoped::<RequestContext>(Arc::new(RequestContextProvider));
+        Context::new(graph)
     }
 }
 
@@ -90,19 +204,146 @@ impl ContrabandApp {
         self
     }
 
+    /// Sets the number of seconds in-flight requests are given to finish during a graceful
+    /// shutdown. Default: 30.
+    pub fn set_shutdown_timeout(mut self, seconds: u64) -> Self {
+        self.app_config.shutdown_timeout = seconds;
+        self
+    }
+
+    /// Disables stopping the application on `SIGINT`, `SIGTERM` and `SIGQUIT`. The application can
+    /// then only be stopped through the [`ShutdownHandle`].
+    pub fn disable_signals(mut self) -> Self {
+        self.app_config.handle_signals = false;
+        self
+    }
+
+    /// Sets the active [`Profile`] of the application. Default: the value of the
+    /// `CONTRABAND_PROFILE` environment variable, if set.
+    pub fn set_profile(mut self, profile: &str) -> Self {
+        self.app_config.profile = Profile::new(profile);
+        self
+    }
+
+    /// Calls the hooks of the interceptor `I` around every route handler of the application, after
+    /// the guards of the route, see [`crate::interceptor`]. The interceptor is resolved from the
+    /// root module once it has been built, and interceptors are called in the order they are
+    /// added.
+    pub fn use_interceptor<I>(mut self) -> Self
+    where
+        I: Interceptor + Injected<Output = I> + 'static,
+    {
+        self.app_config.interceptors.push(interceptor::resolve::<I>);
+        self
+    }
+
+    /// Catches the errors of every route handler of the application with the exception filter
+    /// `F`, unless they are caught by a more specific filter, see [`crate::exception`]. The filter
+    /// is resolved from the root module once it has been built.
+    pub fn use_filter<F>(mut self) -> Self
+    where
+        F: ExceptionFilter + Injected<Output = F> + 'static,
+    {
+        self.app_config.filters.push(exception::resolve::<F>);
+        self
+    }
+
     #[cfg(feature = "rustls")]
     pub fn set_tls_config(mut self, tls_config: RustlsServerConfig) -> Self {
         self.app_config.tls_config = Some(tls_config);
         self
     }
 
-    pub async fn start<T: ModuleFactory>(mut self) -> std::io::Result<()> {
-        let mut listenfd = ListenFd::from_env();
+    async fn build<T: ModuleFactory + 'static>(&mut self) -> Result<Application, ResolutionError> {
+        self.build_with(ModuleRef::of::<T>(), Overrides::default())
+            .await
+    }
+
+    async fn build_with(
+        &mut self,
+        root: ModuleRef,
+        overrides: Overrides,
+    ) -> Result<Application, ResolutionError> {
         let mut ctx: Context = self.app_config.register_global_providers();
-        let module = Arc::new(T::get_module().build(&mut ctx));
+        let declared = DeclaredModule::new(root.clone(), &overrides);
+        ctx.unvalidated_sections = declared.sections();
+        ctx.overrides = overrides;
+        for global in declared.global_modules() {
+            provide_global_module(&mut ctx, global).await?;
+        }
+        let mut module = build_module_ref(&mut ctx, root).await?;
+        let global_providers = &ctx.global_providers;
+        let (interceptors, filters) = module.with_graphs(global_providers, |graph, imports| {
+            let interceptors = self
+                .app_config
+                .interceptors
+                .iter()
+                .map(|resolve| resolve(graph, imports))
+                .collect::<Result<_, _>>()?;
+            let filters = self
+                .app_config
+                .filters
+                .iter()
+                .map(|resolve| resolve(graph, imports))
+                .collect::<Result<_, _>>()?;
+            Ok::<_, GraphResolutionError>((interceptors, filters))
+        })?;
+        let _ = ctx.interceptors.set(interceptors);
+        Ok(Application {
+            module: Arc::new(module),
+            lifecycle: ctx.lifecycle,
+            filters,
+        })
+    }
+
+    /// Resolves the module tree of `T`, including all asynchronous providers, without starting a
+    /// server.
+    ///
+    /// Returns a [`ResolutionError`] describing the first dependency that could not be resolved,
+    /// or all invalid configuration sections, which makes it possible to verify the module setup
+    /// in tests or before deployment.
+    pub async fn try_build<T: ModuleFactory + 'static>(&mut self) -> Result<(), ResolutionError> {
+        self.build::<T>().await.map(|_| ())
+    }
+
+    /// Describes the module tree of `T`, including the providers, exports, controllers and routes
+    /// of each module, without resolving any providers. See [`crate::describe`].
+    pub fn describe<T: ModuleFactory + 'static>(&self) -> ModuleDescription {
+        DeclaredModule::new(ModuleRef::of::<T>(), &Overrides::default())
+            .describe(&self.app_config.profile)
+    }
+
+    /// Resolves the module tree of `T` and starts the server once all asynchronous providers have
+    /// been constructed.
+    ///
+    /// Application bootstrap hooks are called before the server starts accepting connections and
+    /// application shutdown hooks are called, in reverse order, once the server has stopped and
+    /// in-flight requests have been drained.
+    ///
+    /// If the module tree cannot be resolved the [`ResolutionError`] is logged and returned
+    /// wrapped in an [`std::io::Error`] of kind `InvalidInput`.
+    pub async fn start<T: ModuleFactory + 'static>(mut self) -> std::io::Result<()> {
+        let mut listenfd = ListenFd::from_env();
+        let Application {
+            module,
+            mut lifecycle,
+            filters,
+        } = match self.build::<T>().await {
+            Ok(built) => built,
+            Err(err) => {
+                self.app_config.logging_provider.error(err.to_string());
+                return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, err));
+            }
+        };
         let mut server = HttpServer::new(move || {
-            App::new().configure(|cfg| Self::configure(module.clone(), cfg))
-        });
+            App::new()
+                .wrap(UseFilters::global(filters.clone()))
+                .configure(|cfg| Self::configure(module.clone(), cfg))
+        })
+        .shutdown_timeout(self.app_config.shutdown_timeout);
+        if !self.app_config.handle_signals {
+            server = server.disable_signals();
+        }
 
         if cfg!(feature = "rustls") && self.app_config.tls_config.is_some() {
             server = if let Some(l) = listenfd.take_tcp_listener(0).unwrap() {
@@ -121,20 +362,181 @@ impl ContrabandApp {
             }
         };
 
-        server.run().await
+        lifecycle.bootstrap().await;
+        let server = server.run();
+        self.app_config.shutdown_handle.attach(server.clone());
+        let result = server.await;
+        lifecycle.shutdown().await;
+        result
+    }
+
+    pub async fn test_server<T: ModuleFactory + 'static>(
+        self,
+    ) -> TestServer<
+        impl Service<
+            Response = actix_web::dev::ServiceResponse,
+            Request = actix_http::Request,
+            Error = actix_web::Error,
+        >,
+    > {
+        self.test_server_with(ModuleRef::of::<T>(), Overrides::default())
+            .await
     }
 
-    pub async fn test_server<T: ModuleFactory>(
+    pub(crate) async fn test_server_with(
         mut self,
-    ) -> impl Service<
-        Response = actix_web::dev::ServiceResponse,
-        Request = actix_http::Request,
-        Error = actix_web::Error,
+        root: ModuleRef,
+        overrides: Overrides,
+    ) -> TestServer<
+        impl Service<
+            Response = actix_web::dev::ServiceResponse,
+            Request = actix_http::Request,
+            Error = actix_web::Error,
+        >,
     > {
         use actix_web::test;
 
-        let mut ctx: Context = self.app_config.register_global_providers();
-        let module = Arc::new(T::get_module().build(&mut ctx));
-        test::init_service(App::new().configure(|cfg| Self::configure(module.clone(), cfg))).await
+        let Application {
+            module,
+            mut lifecycle,
+            filters,
+        } = self
+            .build_with(root, overrides)
+            .await
+            .unwrap_or_else(|err| panic!("{}", err));
+        lifecycle.bootstrap().await;
+        let service = test::init_service(
+            App::new()
+                .wrap(UseFilters::global(filters))
+                .configure(|cfg| Self::configure(module.clone(), cfg)),
+        )
+        .await;
+        TestServer::new(service, lifecycle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as contraband;
+    use crate::lifecycle::{async_trait, OnApplicationBootstrap, OnApplicationShutdown};
+    use crate::module::Module;
+    use crate::Injectable;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    static SHUT_DOWN: AtomicBool = AtomicBool::new(false);
+
+    #[derive(Clone, Injectable)]
+    struct Stopper {
+        shutdown_handle: ShutdownHandle,
+    }
+
+    #[async_trait(?Send)]
+    impl OnApplicationBootstrap for Stopper {
+        async fn on_application_bootstrap(&self) {
+            self.shutdown_handle.shutdown();
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl OnApplicationShutdown for Stopper {
+        async fn on_application_shutdown(&self) {
+            SHUT_DOWN.store(true, Ordering::SeqCst);
+        }
+    }
+
+    struct StopperModule;
+    impl ModuleFactory for StopperModule {
+        fn get_module() -> Module {
+            Module::new().provide::<Stopper>()
+        }
+    }
+
+    #[contraband::test]
+    async fn test_shutdown_handle_stops_server() {
+        ContrabandApp::new()
+            .set_port(0)
+            .disable_signals()
+            .set_shutdown_timeout(1)
+            .start::<StopperModule>()
+            .await
+            .unwrap();
+        assert!(SHUT_DOWN.load(Ordering::SeqCst));
+    }
+
+    #[contraband::test]
+    async fn test_test_server_calls_shutdown_hooks() {
+        static CLOSED: AtomicBool = AtomicBool::new(false);
+
+        #[derive(Clone, Injectable)]
+        struct Pool;
+
+        #[async_trait(?Send)]
+        impl OnApplicationShutdown for Pool {
+            async fn on_application_shutdown(&self) {
+                CLOSED.store(true, Ordering::SeqCst);
+            }
+        }
+
+        struct PoolModule;
+        impl ModuleFactory for PoolModule {
+            fn get_module() -> Module {
+                Module::new().provide::<Pool>()
+            }
+        }
+
+        let server = ContrabandApp::new().test_server::<PoolModule>().await;
+        assert!(!CLOSED.load(Ordering::SeqCst));
+        server.shutdown().await;
+        assert!(CLOSED.load(Ordering::SeqCst));
+    }
+
+    #[contraband::test]
+    async fn test_global_module_exports_are_reachable_without_import() {
+        #[derive(Clone, Injectable)]
+        struct Metrics;
+
+        #[derive(Clone, Injectable)]
+        struct BookService {
+            metrics: Arc<Metrics>,
+        }
+
+        struct MetricsModule;
+        impl ModuleFactory for MetricsModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .global()
+                    .export::<Metrics>()
+                    .provide::<Metrics>()
+            }
+        }
+
+        struct BookModule;
+        impl ModuleFactory for BookModule {
+            fn get_module() -> Module {
+                Module::new().provide::<BookService>()
+            }
+        }
+
+        struct AppModule;
+        impl ModuleFactory for AppModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .import::<BookModule>()
+                    .import::<MetricsModule>()
+            }
+        }
+
+        let Application { module, .. } = ContrabandApp::new().build::<AppModule>().await.unwrap();
+        let book_module = &module.imported_modules[0];
+        let metrics_module = &module.imported_modules[1];
+        assert!(Arc::ptr_eq(
+            &book_module
+                .graph
+                .get_node::<Arc<BookService>>()
+                .unwrap()
+                .metrics,
+            metrics_module.graph.get_node::<Arc<Metrics>>().unwrap()
+        ));
     }
 }
@@ -0,0 +1,380 @@
+//! Introspection of the module tree of an application
+//!
+//! [`ContrabandApp::describe`](crate::core::ContrabandApp::describe) returns a
+//! [`ModuleDescription`] of the modules declared by an application, including their imports,
+//! providers, exports, controllers and routes. Descriptions can be serialized, or rendered to
+//! JSON and Graphviz DOT in order to generate architecture diagrams.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use contraband::core::ContrabandApp;
+//! use contraband::module;
+//!
+//! #[module]
+//! struct AppModule;
+//!
+//! let description = ContrabandApp::new().describe::<AppModule>();
+//! std::fs::write("architecture.dot", description.to_dot()).unwrap();
+//! ```
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// A module together with the modules it imports.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ModuleDescription {
+    /// Identifies the module within the tree. The type name of the module, numbered for dynamic
+    /// modules since each of them is a module of its own, e.g. `app::GreetingModule#2`.
+    pub id: String,
+    pub name: &'static str,
+    pub global: bool,
+    pub imports: Vec<Self>,
+    pub providers: Vec<ProviderDescription>,
+    /// The type names of the exported providers and values, and the names of re-exported
+    /// modules.
+    pub exports: Vec<&'static str>,
+    /// The names of the exported named providers.
+    pub named_exports: Vec<&'static str>,
+    pub controllers: Vec<ControllerDescription>,
+}
+
+/// The modules of a tree as rendered by [`ModuleDescription::to_json`].
+#[derive(Serialize)]
+struct ModuleTree<'a> {
+    root: &'a str,
+    modules: Vec<ModuleEntry<'a>>,
+}
+
+/// A module whose imports are referred to by id.
+#[derive(Serialize)]
+struct ModuleEntry<'a> {
+    id: &'a str,
+    name: &'static str,
+    global: bool,
+    imports: Vec<&'a str>,
+    providers: &'a [ProviderDescription],
+    exports: &'a [&'static str],
+    named_exports: &'a [&'static str],
+    controllers: &'a [ControllerDescription],
+}
+
+/// A provider declared by a module.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ProviderDescription {
+    pub type_name: &'static str,
+    /// The name of a named provider.
+    pub name: Option<&'static str>,
+    /// The type names of the dependencies of the provider.
+    pub dependencies: Vec<&'static str>,
+}
+
+/// A controller declared by a module.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ControllerDescription {
+    pub type_name: &'static str,
+    pub routes: Vec<RouteDescription>,
+}
+
+/// A route handled by a controller.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RouteDescription {
+    /// The HTTP method of the route, e.g. `GET`.
+    pub method: &'static str,
+    pub path: String,
+    /// The name of the method handling the route.
+    pub handler: &'static str,
+}
+
+impl RouteDescription {
+    /// Describes the route of `handler` at `path` within the scope of its controller.
+    #[doc(hidden)]
+    pub fn new(method: &'static str, scope: &str, path: &str, handler: &'static str) -> Self {
+        let mut path = format!(
+            "{}/{}",
+            scope.trim_end_matches('/'),
+            path.trim_start_matches('/')
+        );
+        if path.len() > 1 && path.ends_with('/') {
+            path.pop();
+        }
+        Self {
+            method,
+            path,
+            handler,
+        }
+    }
+}
+
+impl ModuleDescription {
+    /// Renders the description as pretty-printed JSON, listing every module of the tree once
+    /// with its imports referred to by id, starting with the root module.
+    pub fn to_json(&self) -> String {
+        let mut modules = Vec::new();
+        self.collect_modules(&mut modules, &mut HashSet::new());
+        let tree = ModuleTree {
+            root: &self.id,
+            modules,
+        };
+        serde_json::to_string_pretty(&tree).expect("module descriptions are serializable")
+    }
+
+    fn collect_modules<'a>(
+        &'a self,
+        modules: &mut Vec<ModuleEntry<'a>>,
+        collected: &mut HashSet<&'a str>,
+    ) {
+        if !collected.insert(&self.id) {
+            return;
+        }
+        modules.push(ModuleEntry {
+            id: &self.id,
+            name: self.name,
+            global: self.global,
+            imports: self
+                .imports
+                .iter()
+                .map(|import| import.id.as_str())
+                .collect(),
+            providers: &self.providers,
+            exports: &self.exports,
+            named_exports: &self.named_exports,
+            controllers: &self.controllers,
+        });
+        for import in &self.imports {
+            import.collect_modules(modules, collected);
+        }
+    }
+
+    /// Renders the description as a Graphviz DOT digraph, with modules as boxes, providers as
+    /// ellipses and controllers as notes. Modules imported by more than one module are rendered
+    /// once.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph contraband {\n");
+        self.write_dot(&mut dot, &mut HashSet::new());
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn write_dot<'a>(&'a self, dot: &mut String, rendered: &mut HashSet<&'a str>) {
+        if !rendered.insert(&self.id) {
+            return;
+        }
+        let module = quote(&self.id);
+        let style = if self.global { ", style=bold" } else { "" };
+        let _ = writeln!(
+            dot,
+            "    {} [shape=box, label={}{}];",
+            module,
+            quote(self.name),
+            style
+        );
+        for provider in &self.providers {
+            let label = match provider.name {
+                Some(name) => format!("{} ({})", provider.type_name, name),
+                None => provider.type_name.to_string(),
+            };
+            let node = quote(&format!("{}::{}", self.id, label));
+            let _ = writeln!(
+                dot,
+                "    {} [shape=ellipse, label={}];",
+                node,
+                quote(&label)
+            );
+            let _ = writeln!(dot, "    {} -> {} [style=dashed];", module, node);
+        }
+        for controller in &self.controllers {
+            let routes: Vec<_> = controller
+                .routes
+                .iter()
+                .map(|route| format!("{} {}", route.method, route.path))
+                .collect();
+            let label = std::iter::once(controller.type_name.to_string())
+                .chain(routes)
+                .collect::<Vec<_>>()
+                .join("\n");
+            let node = quote(&format!("{}::{}", self.id, controller.type_name));
+            let _ = writeln!(dot, "    {} [shape=note, label={}];", node, quote(&label));
+            let _ = writeln!(dot, "    {} -> {} [style=dashed];", module, node);
+        }
+        for import in &self.imports {
+            let _ = writeln!(dot, "    {} -> {};", module, quote(&import.id));
+            import.write_dot(dot, rendered);
+        }
+    }
+}
+
+/// Quotes `id` as a DOT identifier.
+fn quote(id: &str) -> String {
+    let escaped = id
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate as contraband;
+    use crate::core::ContrabandApp;
+    use crate::module::{DynamicModule, Module, ModuleFactory};
+    use crate::{controller, Injectable};
+    use actix_web::HttpResponse;
+
+    #[derive(Clone, Injectable)]
+    struct BookService;
+
+    #[derive(Clone, Injectable)]
+    struct BookCache;
+
+    #[derive(Clone, Injectable)]
+    struct BookController;
+
+    #[controller("/books")]
+    impl BookController {
+        #[get]
+        async fn list(self) -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+
+        #[post("/{id}")]
+        async fn update(self) -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+    }
+
+    struct BookModule;
+    impl ModuleFactory for BookModule {
+        fn get_module() -> Module {
+            Module::new()
+                .export::<BookService>()
+                .export_named("isbn_prefix")
+                .provide::<BookService>()
+                .provide_value_named("isbn_prefix", "978")
+                .provide_if::<BookCache>(|profile| profile.is("prod"))
+        }
+    }
+
+    struct ReviewModule;
+    impl ModuleFactory for ReviewModule {
+        fn get_module() -> Module {
+            Module::new().import::<BookModule>()
+        }
+    }
+
+    struct AppModule;
+    impl ModuleFactory for AppModule {
+        fn get_module() -> Module {
+            Module::new()
+                .import::<BookModule>()
+                .import::<ReviewModule>()
+                .controller::<BookController>()
+        }
+    }
+
+    #[test]
+    fn test_module_tree_is_described() {
+        let description = ContrabandApp::new()
+            .set_profile("test")
+            .describe::<AppModule>();
+        assert!(description.name.ends_with("AppModule"));
+        let imported = &description.imports[0];
+        assert!(imported.name.ends_with("BookModule"));
+        assert_eq!(imported.exports.len(), 1);
+        assert!(imported.exports[0].ends_with("BookService"));
+        assert_eq!(imported.named_exports, vec!["isbn_prefix"]);
+        let providers: Vec<_> = imported
+            .providers
+            .iter()
+            .map(|provider| provider.type_name)
+            .collect();
+        assert_eq!(providers.len(), 2);
+        assert!(providers.iter().all(|name| !name.ends_with("BookCache")));
+
+        let controller = &description.controllers[0];
+        assert!(controller.type_name.ends_with("BookController"));
+        assert_eq!(
+            (
+                controller.routes[0].method,
+                controller.routes[0].path.as_str()
+            ),
+            ("GET", "/books")
+        );
+        assert_eq!(
+            (
+                controller.routes[1].method,
+                controller.routes[1].path.as_str()
+            ),
+            ("POST", "/books/{id}")
+        );
+        assert_eq!(controller.routes[1].handler, "update");
+
+        let dot = description.to_dot();
+        assert!(dot.contains(&format!(
+            "\"{}\" -> \"{}\";",
+            description.name, imported.name
+        )));
+
+        let json: serde_json::Value = serde_json::from_str(&description.to_json()).unwrap();
+        assert_eq!(json["root"], description.name);
+        let modules: Vec<_> = json["modules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|module| module["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(modules.len(), 3);
+        assert_eq!(json["modules"][2]["imports"][0], imported.name);
+        assert!(description.to_json().contains("\"path\": \"/books/{id}\""));
+    }
+
+    #[test]
+    fn test_dynamic_modules_are_described_separately() {
+        struct GreetingModule;
+        impl GreetingModule {
+            fn for_feature(greeting: &'static str) -> DynamicModule {
+                DynamicModule::new::<Self>(move || {
+                    Module::new().provide_value_named(greeting, greeting)
+                })
+            }
+        }
+
+        struct AppModule;
+        impl ModuleFactory for AppModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .import_dynamic(GreetingModule::for_feature("Hello"))
+                    .import_dynamic(GreetingModule::for_feature("Hallo"))
+            }
+        }
+
+        let description = ContrabandApp::new().describe::<AppModule>();
+        let name = std::any::type_name::<GreetingModule>();
+        let ids: Vec<_> = description
+            .imports
+            .iter()
+            .map(|import| (import.id.clone(), import.providers[0].name))
+            .collect();
+        assert_eq!(
+            ids,
+            vec![
+                (format!("{}#1", name), Some("Hello")),
+                (format!("{}#2", name), Some("Hallo")),
+            ]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&description.to_json()).unwrap();
+        let providers: Vec<_> = json["modules"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|module| module["name"] == name)
+            .map(|module| module["providers"][0]["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(providers, vec!["Hello", "Hallo"]);
+
+        let dot = description.to_dot();
+        assert!(dot.contains(&format!("\"{}#1::&str (Hello)\"", name)));
+        assert!(dot.contains(&format!("\"{}#2::&str (Hallo)\"", name)));
+    }
+}
@@ -0,0 +1,552 @@
+//! Exception filters mapping errors of route handlers to responses
+//!
+//! Route handlers of a [controller](../attr.controller.html) may return `Result<T, E>` for any
+//! error type `E` implementing `Display`. Errors are wrapped in an [`Exception`], which is
+//! responded to with `500 Internal Server Error` as a JSON [`Problem`] unless it is caught by an
+//! exception filter. Errors implementing `ResponseError` that are not caught are responded to
+//! with their own response.
+//!
+//! An exception filter is an injectable provider implementing [`ExceptionFilter`] for the errors
+//! of type [`ExceptionFilter::Error`]. Filters are attached to single routes or to a controller
+//! with `#[use_filters(...)]`, to all controllers of a module with
+//! [`Module::use_filter`](crate::module::Module::use_filter), or to all routes of an application
+//! with [`ContrabandApp::use_filter`](crate::core::ContrabandApp::use_filter). The most specific
+//! filter catching an error is used, i.e. filters of the route come before those of the
+//! controller, the module and the application.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use actix_web::http::StatusCode;
+//! use actix_web::{HttpRequest, HttpResponse};
+//! use contraband::exception::{ExceptionFilter, Problem};
+//! use contraband::{controller, Injectable};
+//!
+//! #[derive(Debug)]
+//! struct NotFound(i32);
+//!
+//! impl std::fmt::Display for NotFound {
+//!     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+//!         write!(f, "Book {} not found", self.0)
+//!     }
+//! }
+//!
+//! #[derive(Clone, Injectable)]
+//! struct NotFoundFilter;
+//!
+//! impl ExceptionFilter for NotFoundFilter {
+//!     type Error = NotFound;
+//!
+//!     fn catch(&self, error: &NotFound, _: &HttpRequest) -> HttpResponse {
+//!         Problem::new(StatusCode::NOT_FOUND)
+//!             .with_detail(error.to_string())
+//!             .into()
+//!     }
+//! }
+//!
+//! #[derive(Clone, Injectable)]
+//! struct BookController;
+//!
+//! #[controller("/books")]
+//! #[use_filters(NotFoundFilter)]
+//! impl BookController {
+//!     #[get("/{id}")]
+//!     async fn get(self) -> Result<HttpResponse, NotFound> {
+//!         Err(NotFound(1))
+//!     }
+//! }
+//! ```
+use crate::graph::{Graph, Injected, ResolutionError};
+use crate::module::ControllerProvider;
+use crate::pipe::FieldError;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpRequest, HttpResponse, ResponseError};
+use futures_util::future::{ok, FutureExt, LocalBoxFuture, Ready};
+use serde::Serialize;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Returns the boxed error of an exception as a `ResponseError`.
+type AsResponseError = fn(&dyn Any) -> &dyn ResponseError;
+
+/// An error returned by a route handler.
+pub struct Exception {
+    error: Box<dyn Any>,
+    type_name: &'static str,
+    message: String,
+    response_error: Option<AsResponseError>,
+}
+
+impl Exception {
+    pub fn new<E: fmt::Display + 'static>(error: E) -> Self {
+        Self {
+            message: error.to_string(),
+            type_name: std::any::type_name::<E>(),
+            error: Box::new(error),
+            response_error: None,
+        }
+    }
+
+    /// Wraps `error`, which is responded to with its own response unless it is caught by an
+    /// exception filter.
+    pub fn from_response_error<E: ResponseError + 'static>(error: E) -> Self {
+        Self {
+            response_error: Some(|error| {
+                error
+                    .downcast_ref::<E>()
+                    .expect("Exceptions are created with errors of their type")
+            }),
+            ..Self::new(error)
+        }
+    }
+
+    /// Returns the error if it is of type `E`.
+    pub fn downcast_ref<E: 'static>(&self) -> Option<&E> {
+        self.error.downcast_ref::<E>()
+    }
+}
+
+impl fmt::Debug for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Exception")
+            .field("type_name", &self.type_name)
+            .field("message", &self.message)
+            .finish()
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Exception {
+    fn as_response_error(&self) -> Option<&dyn ResponseError> {
+        match self.response_error {
+            Some(as_response_error) => Some(as_response_error(&*self.error)),
+            None => self
+                .downcast_ref::<Error>()
+                .map(|error| error.as_response_error()),
+        }
+    }
+}
+
+impl ResponseError for Exception {
+    fn status_code(&self) -> StatusCode {
+        match self.as_response_error() {
+            Some(error) => error.status_code(),
+            None => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Responds with the response of the error if it is a `ResponseError` or an
+    /// `actix_web::Error`, otherwise with a [`Problem`] without details, since these are not
+    /// meant for clients.
+    fn error_response(&self) -> HttpResponse {
+        match self.as_response_error() {
+            Some(error) => error.error_response(),
+            None => Problem::new(StatusCode::INTERNAL_SERVER_ERROR).into(),
+        }
+    }
+}
+
+/// Used by the [controller](../attr.controller.html) macro to wrap the errors of route handlers
+/// in an [`Exception`].
+///
+/// Errors are probed through auto-ref specialization: [`IntoResponseException`] is implemented
+/// for `&ExceptionProbe<E>` if `E` implements `ResponseError`, while [`IntoException`] is
+/// implemented for all `ExceptionProbe<E>` of displayable `E`.
+#[doc(hidden)]
+pub struct ExceptionProbe<E>(pub PhantomData<E>);
+
+#[doc(hidden)]
+pub trait IntoException<E> {
+    fn exception(&self, error: E) -> Exception;
+}
+
+impl<E: fmt::Display + 'static> IntoException<E> for ExceptionProbe<E> {
+    fn exception(&self, error: E) -> Exception {
+        Exception::new(error)
+    }
+}
+
+#[doc(hidden)]
+pub trait IntoResponseException<E> {
+    fn exception(&self, error: E) -> Exception;
+}
+
+impl<E: ResponseError + 'static> IntoResponseException<E> for &ExceptionProbe<E> {
+    fn exception(&self, error: E) -> Exception {
+        Exception::from_response_error(error)
+    }
+}
+
+/// A JSON problem response as described by RFC 7807, with the content type
+/// `application/problem+json`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Problem {
+    pub status: u16,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The errors of invalid fields, see [`pipe`](crate::pipe).
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+}
+
+impl Problem {
+    /// Creates a problem titled after the reason phrase of `status`.
+    pub fn new(status: StatusCode) -> Self {
+        Self {
+            status: status.as_u16(),
+            title: status.canonical_reason().unwrap_or_default().to_string(),
+            detail: None,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+}
+
+impl From<Problem> for HttpResponse {
+    fn from(problem: Problem) -> Self {
+        let status =
+            StatusCode::from_u16(problem.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        Self::build(status)
+            .content_type("application/problem+json")
+            .body(serde_json::to_string(&problem).expect("problems are serializable"))
+    }
+}
+
+/// Maps errors of type `Error` returned by route handlers to responses.
+pub trait ExceptionFilter: Send + Sync {
+    type Error: 'static;
+
+    fn catch(&self, error: &Self::Error, req: &HttpRequest) -> HttpResponse;
+}
+
+/// An exception filter for any type of error.
+pub(crate) trait Catch: Send + Sync {
+    /// Returns the response of the filter if it catches `exception`.
+    fn try_catch(&self, exception: &Exception, req: &HttpRequest) -> Option<HttpResponse>;
+}
+
+impl<F: ExceptionFilter> Catch for F {
+    fn try_catch(&self, exception: &Exception, req: &HttpRequest) -> Option<HttpResponse> {
+        exception
+            .downcast_ref::<F::Error>()
+            .map(|error| self.catch(error, req))
+    }
+}
+
+pub(crate) type FilterFactory =
+    fn(&mut Graph, &[&Graph]) -> Result<Arc<dyn Catch>, ResolutionError>;
+
+/// Resolves the exception filter `F` of a module or the application.
+pub(crate) fn resolve<F>(
+    graph: &mut Graph,
+    imports: &[&Graph],
+) -> Result<Arc<dyn Catch>, ResolutionError>
+where
+    F: ExceptionFilter + Injected<Output = F> + 'static,
+{
+    Ok(graph.get_or_resolve::<Arc<F>>(imports)?)
+}
+
+type Lookup = Rc<dyn Fn(&HttpRequest) -> Vec<Arc<dyn Catch>>>;
+
+/// Middleware catching the exceptions of the wrapped service with exception filters. Generated by
+/// the [controller](../attr.controller.html) macro for every route.
+#[doc(hidden)]
+pub struct UseFilters {
+    lookup: Lookup,
+}
+
+impl UseFilters {
+    /// Catches the exceptions of a route of the controller `T` with its filters of `type_ids`,
+    /// followed by the filters of its module.
+    pub fn of<T: 'static>(type_ids: Vec<TypeId>) -> Self {
+        Self {
+            lookup: Rc::new(move |req| {
+                let provider = ControllerProvider::<T>::of(req);
+                provider
+                    .enhancers
+                    .filters
+                    .select(&type_ids)
+                    .chain(provider.module_filters.iter().cloned())
+                    .collect()
+            }),
+        }
+    }
+
+    /// Catches the exceptions of all routes with `filters`.
+    pub(crate) fn global(filters: Vec<Arc<dyn Catch>>) -> Self {
+        Self {
+            lookup: Rc::new(move |_| filters.clone()),
+        }
+    }
+}
+
+impl<S> Transform<S> for UseFilters
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = UseFiltersMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(UseFiltersMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            lookup: self.lookup.clone(),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct UseFiltersMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    lookup: Lookup,
+}
+
+impl<S> Service for UseFiltersMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let fut = self.service.borrow_mut().call(req);
+        let lookup = self.lookup.clone();
+        async move {
+            let res = fut.await?;
+            let caught = match res
+                .response()
+                .error()
+                .and_then(|error| error.as_error::<Exception>())
+            {
+                Some(exception) => lookup(res.request())
+                    .iter()
+                    .find_map(|filter| filter.try_catch(exception, res.request())),
+                None => None,
+            };
+            match caught {
+                Some(caught) => Ok(ServiceResponse::new(res.request().clone(), caught)),
+                None => Ok(res),
+            }
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as contraband;
+    use crate::core::ContrabandApp;
+    use crate::module::{Module, ModuleFactory};
+    use crate::{controller, Injectable};
+    use actix_web::test;
+
+    /// Defines the error `$error` and a filter responding to it with a problem of `$status`.
+    macro_rules! error_filter {
+        ($error:ident, $filter:ident, $status:expr) => {
+            #[derive(Debug)]
+            struct $error;
+
+            impl fmt::Display for $error {
+                fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                    f.write_str(stringify!($error))
+                }
+            }
+
+            #[derive(Clone, Injectable)]
+            struct $filter;
+
+            impl ExceptionFilter for $filter {
+                type Error = $error;
+
+                fn catch(&self, error: &$error, _: &HttpRequest) -> HttpResponse {
+                    Problem::new($status).with_detail(error.to_string()).into()
+                }
+            }
+        };
+    }
+
+    error_filter!(NotFound, NotFoundFilter, StatusCode::NOT_FOUND);
+    error_filter!(Conflict, ConflictFilter, StatusCode::CONFLICT);
+    error_filter!(Invalid, InvalidFilter, StatusCode::BAD_REQUEST);
+    error_filter!(
+        Unavailable,
+        UnavailableFilter,
+        StatusCode::SERVICE_UNAVAILABLE
+    );
+
+    /// Catches `NotFound` like `NotFoundFilter`, but with `410 Gone`.
+    #[derive(Clone, Injectable)]
+    struct GoneFilter;
+
+    impl ExceptionFilter for GoneFilter {
+        type Error = NotFound;
+
+        fn catch(&self, _: &NotFound, _: &HttpRequest) -> HttpResponse {
+            Problem::new(StatusCode::GONE).into()
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct BookController;
+
+    #[controller("/books")]
+    #[use_filters(ConflictFilter, NotFoundFilter)]
+    impl BookController {
+        #[get("/gone")]
+        #[use_filters(GoneFilter)]
+        async fn gone(self) -> Result<HttpResponse, NotFound> {
+            Err(NotFound)
+        }
+
+        #[get("/missing")]
+        async fn missing(self) -> Result<HttpResponse, NotFound> {
+            Err(NotFound)
+        }
+
+        #[get("/conflict")]
+        async fn conflict(self) -> Result<HttpResponse, Conflict> {
+            Err(Conflict)
+        }
+
+        #[get("/invalid")]
+        async fn invalid(self) -> Result<HttpResponse, Invalid> {
+            Err(Invalid)
+        }
+
+        #[get("/unavailable")]
+        async fn unavailable(self) -> Result<HttpResponse, Unavailable> {
+            Err(Unavailable)
+        }
+
+        #[get("/unknown")]
+        async fn unknown(self) -> Result<HttpResponse, fmt::Error> {
+            Err(fmt::Error)
+        }
+
+        #[get("/found")]
+        async fn found(self) -> Result<&'static str, NotFound> {
+            Ok("found")
+        }
+    }
+
+    struct AppModule;
+    impl ModuleFactory for AppModule {
+        fn get_module() -> Module {
+            Module::new()
+                .use_filter::<InvalidFilter>()
+                .controller::<BookController>()
+        }
+    }
+
+    #[derive(Debug)]
+    struct Locked;
+
+    impl fmt::Display for Locked {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Locked")
+        }
+    }
+
+    impl ResponseError for Locked {
+        fn error_response(&self) -> HttpResponse {
+            HttpResponse::build(StatusCode::LOCKED).body("locked")
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct ArchiveController;
+
+    #[controller("/archive")]
+    impl ArchiveController {
+        #[get]
+        async fn list(self) -> Result<HttpResponse, Locked> {
+            Err(Locked)
+        }
+    }
+
+    struct ArchiveModule;
+    impl ModuleFactory for ArchiveModule {
+        fn get_module() -> Module {
+            Module::new().controller::<ArchiveController>()
+        }
+    }
+
+    #[contraband::test]
+    async fn test_uncaught_response_errors_keep_their_response() {
+        let mut server = ContrabandApp::new().test_server::<ArchiveModule>().await;
+        let req = test::TestRequest::get().uri("/archive").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.status(), StatusCode::LOCKED);
+        assert_eq!(test::read_body(res).await, "locked");
+    }
+
+    #[contraband::test]
+    async fn test_exception_filters_map_errors_to_responses() {
+        let mut server = ContrabandApp::new()
+            .use_filter::<UnavailableFilter>()
+            .test_server::<AppModule>()
+            .await;
+        let cases = vec![
+            ("/books/gone", StatusCode::GONE),
+            ("/books/missing", StatusCode::NOT_FOUND),
+            ("/books/conflict", StatusCode::CONFLICT),
+            ("/books/invalid", StatusCode::BAD_REQUEST),
+            ("/books/unavailable", StatusCode::SERVICE_UNAVAILABLE),
+            ("/books/unknown", StatusCode::INTERNAL_SERVER_ERROR),
+            ("/books/found", StatusCode::OK),
+        ];
+        for (uri, status) in cases {
+            let req = test::TestRequest::get().uri(uri).to_request();
+            let res = test::call_service(&mut server, req).await;
+            assert_eq!(res.status(), status, "{}", uri);
+        }
+
+        let req = test::TestRequest::get().uri("/books/missing").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(
+            res.headers().get("content-type").unwrap(),
+            "application/problem+json"
+        );
+        assert_eq!(
+            test::read_body(res).await,
+            r#"{"status":404,"title":"Not Found","detail":"NotFound"}"#
+        );
+
+        let req = test::TestRequest::get().uri("/books/unknown").to_request();
+        let body = test::read_response(&mut server, req).await;
+        assert_eq!(body, r#"{"status":500,"title":"Internal Server Error"}"#);
+    }
+}
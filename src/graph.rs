@@ -1,7 +1,342 @@
+use crate::config::SectionError;
+use crate::core::Profile;
+use crate::lifecycle::Hooks;
+use crate::module::Scope;
+use once_cell::sync::OnceCell;
 use std::any::{Any, TypeId};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
 
+/// Error returned when the dependency graph of a module cannot be resolved.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ResolutionError {
+    /// No provider of `type_name`, with `name` if it is a named provider, is reachable from the
+    /// module being built.
+    MissingProvider {
+        type_name: &'static str,
+        name: Option<&'static str>,
+        required_by: Option<&'static str>,
+        field: Option<&'static str>,
+        module: Option<&'static str>,
+    },
+    /// A module transitively imports itself. The chain starts and ends with the same module.
+    ImportCycle { chain: Vec<&'static str> },
+    /// A provider transitively depends on itself. The chain starts and ends with the same
+    /// provider.
+    DependencyCycle {
+        chain: Vec<&'static str>,
+        module: Option<&'static str>,
+    },
+    /// A request-scoped provider was injected outside of an HTTP request, e.g. into a singleton.
+    InvalidScope {
+        type_name: &'static str,
+        module: Option<&'static str>,
+    },
+    /// The asynchronous factory of `type_name` returned an error.
+    FactoryFailed {
+        type_name: &'static str,
+        message: String,
+        module: Option<&'static str>,
+    },
+    /// The module `reexported` is re-exported by a module that does not import it.
+    MissingImport {
+        reexported: &'static str,
+        module: Option<&'static str>,
+    },
+    /// Keys of configuration sections are missing or do not match the types of their fields.
+    /// `sources` lists where the configuration was loaded from.
+    InvalidConfig {
+        errors: Vec<SectionError>,
+        sources: Vec<String>,
+    },
+}
+
+impl ResolutionError {
+    #[doc(hidden)]
+    pub fn missing<T: ?Sized>() -> Self {
+        Self::MissingProvider {
+            type_name: std::any::type_name::<T>(),
+            name: None,
+            required_by: None,
+            field: None,
+            module: None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn missing_field<T: ?Sized, S: ?Sized>(field: &'static str) -> Self {
+        Self::MissingProvider {
+            type_name: std::any::type_name::<T>(),
+            name: None,
+            required_by: Some(std::any::type_name::<S>()),
+            field: Some(field),
+            module: None,
+        }
+    }
+
+    #[doc(hidden)]
+    pub fn missing_named_field<T: ?Sized, S: ?Sized>(
+        field: &'static str,
+        name: &'static str,
+    ) -> Self {
+        Self::MissingProvider {
+            type_name: std::any::type_name::<T>(),
+            name: Some(name),
+            required_by: Some(std::any::type_name::<S>()),
+            field: Some(field),
+            module: None,
+        }
+    }
+
+    /// Attaches the name of the module being built, unless a more specific module is already
+    /// known.
+    pub(crate) fn in_module(mut self, name: &'static str) -> Self {
+        match &mut self {
+            Self::MissingProvider { module, .. }
+            | Self::DependencyCycle { module, .. }
+            | Self::InvalidScope { module, .. }
+            | Self::FactoryFailed { module, .. }
+            | Self::MissingImport { module, .. } => {
+                module.get_or_insert(name);
+            }
+            Self::InvalidConfig { errors, .. } => {
+                for error in errors {
+                    error.module.get_or_insert(name);
+                }
+            }
+            Self::ImportCycle { .. } => {}
+        }
+        self
+    }
+}
+
+impl fmt::Display for ResolutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingProvider {
+                type_name,
+                name,
+                required_by,
+                field,
+                module,
+            } => {
+                write!(f, "No provider found for `{}`", type_name)?;
+                if let Some(name) = name {
+                    write!(f, " named `{}`", name)?;
+                }
+                match (required_by, field) {
+                    (Some(required_by), Some(field)) => {
+                        write!(f, ", required by field `{}` of `{}`", field, required_by)?
+                    }
+                    (Some(required_by), None) => write!(f, ", required by `{}`", required_by)?,
+                    _ => {}
+                }
+                if let Some(module) = module {
+                    write!(f, " in module `{}`", module)?;
+                }
+                write!(f, ". Is it provided and exported by an imported module?")
+            }
+            Self::ImportCycle { chain } => {
+                write!(f, "Circular module import: {}", chain.join(" -> "))
+            }
+            Self::DependencyCycle { chain, module } => {
+                write!(f, "Circular provider dependency")?;
+                if let Some(module) = module {
+                    write!(f, " in module `{}`", module)?;
+                }
+                write!(f, ": {}", chain.join(" -> "))
+            }
+            Self::InvalidScope { type_name, module } => {
+                write!(f, "Request-scoped provider `{}` injected", type_name)?;
+                if let Some(module) = module {
+                    write!(f, " in module `{}`", module)?;
+                }
+                write!(
+                    f,
+                    " outside of a request. Request-scoped providers can only be injected into \
+                     controllers and other request-scoped providers."
+                )
+            }
+            Self::FactoryFailed {
+                type_name,
+                message,
+                module,
+            } => {
+                write!(f, "Factory of `{}` failed", type_name)?;
+                if let Some(module) = module {
+                    write!(f, " in module `{}`", module)?;
+                }
+                write!(f, ": {}", message)
+            }
+            Self::MissingImport { reexported, module } => {
+                write!(f, "Module `{}` is re-exported", reexported)?;
+                if let Some(module) = module {
+                    write!(f, " by module `{}`", module)?;
+                }
+                write!(f, " without being imported")
+            }
+            Self::InvalidConfig { errors, sources } => {
+                write!(f, "Invalid configuration:")?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                if !sources.is_empty() {
+                    write!(f, "\nConfiguration loaded from: {}", sources.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResolutionError {}
+
+/// A dependency of an injectable struct, declared by one of its fields.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependency {
+    pub type_id: TypeId,
+    pub type_name: &'static str,
+    pub name: Option<&'static str>,
+    pub field: &'static str,
+    /// Whether the dependent can be resolved without the dependency.
+    pub optional: bool,
+}
+
+impl Dependency {
+    pub fn of<T: 'static>(field: &'static str) -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            name: None,
+            field,
+            optional: false,
+        }
+    }
+
+    /// A dependency on the provider of `T` named `name`.
+    pub fn named<T: 'static>(field: &'static str, name: &'static str) -> Self {
+        Self {
+            name: Some(name),
+            ..Self::of::<T>(field)
+        }
+    }
+
+    /// Marks the dependency as optional.
+    pub fn optional(self) -> Self {
+        Self {
+            optional: true,
+            ..self
+        }
+    }
+}
+
+/// A dependency that is injected as `None` if it is not reachable from the module of the
+/// dependent, such as an `Option<Arc<T>>` field.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::Injectable;
+/// use std::sync::Arc;
+///
+/// #[derive(Clone, Injectable)]
+/// struct MetricsRegistry;
+///
+/// #[derive(Clone, Injectable)]
+/// struct BookService {
+///     metrics: Option<Arc<MetricsRegistry>>,
+/// }
+/// ```
+pub trait Optional {
+    type Inner;
+}
+
+impl<T> Optional for Option<T> {
+    type Inner = T;
+}
+
+/// A provider of a module together with the dependencies it needs to be resolved.
+pub(crate) struct ProviderNode {
+    pub(crate) type_id: TypeId,
+    pub(crate) type_name: &'static str,
+    pub(crate) name: Option<&'static str>,
+    pub(crate) dependencies: Vec<Dependency>,
+    /// The condition on the active profile under which the provider is declared, see
+    /// `Module::provide_if`.
+    pub(crate) condition: Option<Condition>,
+}
+
+pub(crate) type Condition = Box<dyn FnOnce(&Profile) -> bool>;
+
+impl ProviderNode {
+    /// Whether the provider is declared for `profile`.
+    pub(crate) fn is_enabled(&mut self, profile: &Profile) -> bool {
+        match self.condition.take() {
+            Some(condition) => condition(profile),
+            None => true,
+        }
+    }
+}
+
+/// Sorts `nodes` such that every provider comes after the providers it depends on, and returns
+/// their indices in that order. Providers that do not depend on each other keep their order of
+/// declaration. Dependencies on types outside of `nodes` are ignored, since they are resolved
+/// from imported modules.
+///
+/// Returns the names of the providers along a dependency cycle if there is one.
+pub(crate) fn resolution_order(nodes: &[ProviderNode]) -> Result<Vec<usize>, Vec<&'static str>> {
+    fn visit(
+        index: usize,
+        nodes: &[ProviderNode],
+        indices: &HashMap<(TypeId, Option<&'static str>), Vec<usize>>,
+        visited: &mut HashSet<usize>,
+        path: &mut Vec<usize>,
+        order: &mut Vec<usize>,
+    ) -> Result<(), Vec<&'static str>> {
+        if let Some(pos) = path.iter().position(|&x| x == index) {
+            let mut chain: Vec<_> = path[pos..].iter().map(|&x| nodes[x].type_name).collect();
+            chain.push(nodes[index].type_name);
+            return Err(chain);
+        }
+        if !visited.insert(index) {
+            return Ok(());
+        }
+        path.push(index);
+        for dependency in &nodes[index].dependencies {
+            let providers = indices.get(&(dependency.type_id, dependency.name));
+            for &next in providers.into_iter().flatten() {
+                visit(next, nodes, indices, visited, path, order)?;
+            }
+        }
+        path.pop();
+        order.push(index);
+        Ok(())
+    }
+
+    // A type can have several providers, e.g. the contributions to a multi-binding.
+    let mut indices: HashMap<_, Vec<usize>> = HashMap::new();
+    for (i, node) in nodes.iter().enumerate() {
+        indices
+            .entry((node.type_id, node.name))
+            .or_default()
+            .push(i);
+    }
+    let mut visited = HashSet::new();
+    let mut order = Vec::with_capacity(nodes.len());
+    for i in 0..nodes.len() {
+        visit(
+            i,
+            nodes,
+            &indices,
+            &mut visited,
+            &mut Vec::new(),
+            &mut order,
+        )?;
+    }
+    Ok(order)
+}
+
 #[derive(Debug)]
 pub struct Value<T>(pub T);
 
@@ -19,48 +354,319 @@ impl<T: Clone> Clone for Value<T> {
     }
 }
 
+/// A dependency that is resolved once the module it is injected in has been fully built.
+///
+/// `Lazy` makes it possible for two providers of the same module to depend on each other, since
+/// the target of a lazy dependency is not required to be resolved before the dependent provider.
+/// The target can be accessed through `Deref` once the module has been built; accessing it
+/// earlier, e.g. while the module is being built, panics.
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::graph::Lazy;
+/// use contraband::{module, Injectable};
+/// use std::sync::Arc;
+///
+/// #[derive(Clone, Injectable)]
+/// struct UserService {
+///     auth_service: Lazy<AuthService>,
+/// }
+///
+/// #[derive(Clone, Injectable)]
+/// struct AuthService {
+///     user_service: Arc<UserService>,
+/// }
+///
+/// #[module]
+/// #[provider(UserService, AuthService)]
+/// struct UserModule;
+/// ```
+pub struct Lazy<T> {
+    cell: Arc<OnceCell<Arc<T>>>,
+}
+
+impl<T: Send + Sync + 'static> Lazy<T> {
+    /// Creates an unresolved `Lazy` and registers it to be resolved from `graph` once the module
+    /// has been built.
+    #[doc(hidden)]
+    pub fn deferred<S: ?Sized>(graph: &mut Graph, field: &'static str) -> Self {
+        let cell = Arc::new(OnceCell::new());
+        graph.deferred.push(Arc::new(DeferredLazy {
+            cell: cell.clone(),
+            required_by: std::any::type_name::<S>(),
+            field,
+        }));
+        Self { cell }
+    }
+
+    /// Returns the resolved dependency, or `None` if the module has not been built yet.
+    pub fn get(&self) -> Option<&Arc<T>> {
+        self.cell.get()
+    }
+}
+
+impl<T> Clone for Lazy<T> {
+    fn clone(&self) -> Self {
+        Self {
+            cell: self.cell.clone(),
+        }
+    }
+}
+
+impl<T> std::ops::Deref for Lazy<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        match self.cell.get() {
+            Some(ptr) => ptr,
+            None => panic!(
+                "Lazy dependency `{}` accessed before its module was built",
+                std::any::type_name::<T>()
+            ),
+        }
+    }
+}
+
+impl<T> fmt::Debug for Lazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy")
+            .field("type_name", &std::any::type_name::<T>())
+            .field("resolved", &self.cell.get().is_some())
+            .finish()
+    }
+}
+
+pub(crate) trait Deferred: fmt::Debug + Send + Sync {
+    fn resolve(&self, graph: &Graph, imported_graphs: &[&Graph]) -> Result<(), ResolutionError>;
+}
+
+struct DeferredLazy<T> {
+    cell: Arc<OnceCell<Arc<T>>>,
+    required_by: &'static str,
+    field: &'static str,
+}
+
+impl<T: Send + Sync + 'static> Deferred for DeferredLazy<T> {
+    fn resolve(&self, graph: &Graph, imported_graphs: &[&Graph]) -> Result<(), ResolutionError> {
+        let ptr = graph
+            .get_node::<Arc<T>>()
+            .or_else(|| Graph::search_all(imported_graphs))
+            .ok_or_else(|| ResolutionError::MissingProvider {
+                type_name: std::any::type_name::<Arc<T>>(),
+                name: None,
+                required_by: Some(self.required_by),
+                field: Some(self.field),
+                module: None,
+            })?;
+        let _ = self.cell.set(ptr.clone());
+        Ok(())
+    }
+}
+
+impl<T> fmt::Debug for DeferredLazy<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeferredLazy")
+            .field("type_name", &std::any::type_name::<T>())
+            .field("required_by", &self.required_by)
+            .field("field", &self.field)
+            .finish()
+    }
+}
+
+/// The graphs a module resolves the dependencies of its providers from, captured once the module
+/// has been built.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ResolutionScope {
+    pub(crate) graph: Graph,
+    pub(crate) imports: Vec<Graph>,
+}
+
+impl ResolutionScope {
+    /// Resolves a new instance of `T` from the scope. The graphs of the scope are shared, the
+    /// instance is resolved into an empty graph of its own, e.g. for each request.
+    pub(crate) fn resolve<T: Injected<Output = T>>(&self) -> Result<T, ResolutionError> {
+        let mut overlay = Graph::new();
+        let imports: Vec<&Graph> = std::iter::once(&self.graph)
+            .chain(self.imports.iter())
+            .collect();
+        let instance = T::resolve(&mut overlay, &imports)?;
+        for deferred in overlay.take_deferred() {
+            deferred.resolve(&overlay, &imports)?;
+        }
+        Ok(instance)
+    }
+}
+
+/// A provider that creates new instances on demand rather than being stored as a singleton.
+pub(crate) trait ScopedProvider: fmt::Debug + Send + Sync {
+    fn scope(&self) -> Scope;
+
+    /// The dependencies of the instances created by the provider.
+    fn dependencies(&self) -> Vec<Dependency>;
+
+    fn create(
+        &self,
+        graph: &mut Graph,
+        imported_graphs: &[&Graph],
+    ) -> Result<Arc<dyn Any + Send + Sync>, ResolutionError>;
+}
+
+/// The implementations contributed to an interface, i.e. a `Vec<Arc<I>>`.
+pub(crate) trait Contributions: Send + Sync {
+    fn as_any(&self) -> &(dyn Any + Send + Sync);
+
+    /// Returns the contributions of `self` followed by those of `other` that are not in `self`.
+    fn merge(&self, other: &dyn Contributions) -> Arc<dyn Contributions>;
+}
+
+impl<I: ?Sized + Send + Sync + 'static> Contributions for Vec<Arc<I>> {
+    fn as_any(&self) -> &(dyn Any + Send + Sync) {
+        self
+    }
+
+    fn merge(&self, other: &dyn Contributions) -> Arc<dyn Contributions> {
+        let mut merged = self.clone();
+        if let Some(other) = other.as_any().downcast_ref::<Self>() {
+            for instance in other {
+                let ptr = Arc::as_ptr(instance) as *const ();
+                if !merged.iter().any(|x| Arc::as_ptr(x) as *const () == ptr) {
+                    merged.push(instance.clone());
+                }
+            }
+        }
+        Arc::new(merged)
+    }
+}
+
+impl fmt::Debug for dyn Contributions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Contributions").finish()
+    }
+}
+
 pub trait Injected: Send + Sync {
     type Output: Injected;
-    fn resolve(graph: &mut Graph, imported_graphs: &[&Graph]) -> Self::Output
+    fn resolve(
+        graph: &mut Graph,
+        imported_graphs: &[&Graph],
+    ) -> Result<Self::Output, ResolutionError>
     where
         Self: Sized;
+
+    /// Returns the dependencies that need to be provided in order to resolve `Self`.
+    fn dependencies() -> Vec<Dependency>
+    where
+        Self: Sized,
+    {
+        Vec::new()
+    }
+
+    /// Returns the lifecycle hooks implemented by `instance`.
+    fn hooks(_instance: &Arc<Self>) -> Hooks
+    where
+        Self: Sized,
+    {
+        Hooks::default()
+    }
 }
 
 impl<T: Send + Sync> Injected for Value<T> {
     type Output = Self;
-    fn resolve(_graph: &mut Graph, _imported_graphs: &[&Graph]) -> Self::Output {
-        panic!(
-            "Data type has not been provided: {}",
-            std::any::type_name::<T>()
-        )
+    fn resolve(
+        _graph: &mut Graph,
+        _imported_graphs: &[&Graph],
+    ) -> Result<Self::Output, ResolutionError> {
+        Err(ResolutionError::missing::<Self>())
     }
 }
 
 impl<T: Injected<Output = T>> Injected for Arc<T> {
     type Output = Self;
-    fn resolve(graph: &mut Graph, imported_graphs: &[&Graph]) -> Self::Output {
-        T::resolve(graph, imported_graphs).into()
+    fn resolve(
+        graph: &mut Graph,
+        imported_graphs: &[&Graph],
+    ) -> Result<Self::Output, ResolutionError> {
+        T::resolve(graph, imported_graphs).map(Self::from)
+    }
+
+    fn dependencies() -> Vec<Dependency> {
+        T::dependencies()
     }
 }
 
+macro_rules! impl_injected_tuple {
+    ($($name:ident $index:tt),*) => {
+        impl<$($name),*> Injected for ($($name,)*)
+        where
+            $($name: Injected<Output = $name> + Clone + 'static,)*
+        {
+            type Output = Self;
+            #[allow(unused_variables)]
+            fn resolve(
+                graph: &mut Graph,
+                imported_graphs: &[&Graph],
+            ) -> Result<Self::Output, ResolutionError> {
+                Ok(($(
+                    graph.inject::<$name>(imported_graphs)?.ok_or_else(|| {
+                        ResolutionError::missing_field::<$name, Self>(stringify!($index))
+                    })?,
+                )*))
+            }
+
+            fn dependencies() -> Vec<Dependency> {
+                vec![$(Dependency::of::<$name>(stringify!($index))),*]
+            }
+        }
+    };
+}
+
+impl_injected_tuple!();
+impl_injected_tuple!(A 0);
+impl_injected_tuple!(A 0, B 1);
+impl_injected_tuple!(A 0, B 1, C 2);
+impl_injected_tuple!(A 0, B 1, C 2, D 3);
+impl_injected_tuple!(A 0, B 1, C 2, D 3, E 4);
+impl_injected_tuple!(A 0, B 1, C 2, D 3, E 4, F 5);
+
 #[derive(Clone, Debug, Default)]
 pub struct Graph {
     map: HashMap<TypeId, Arc<(dyn Send + Sync + Any)>>,
+    named: HashMap<(TypeId, &'static str), Arc<dyn Send + Sync + Any>>,
+    multi: HashMap<TypeId, Arc<dyn Contributions>>,
+    scoped: HashMap<TypeId, Arc<dyn ScopedProvider>>,
+    deferred: Vec<Arc<dyn Deferred>>,
 }
 
 impl Graph {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            named: HashMap::new(),
+            multi: HashMap::new(),
+            scoped: HashMap::new(),
+            deferred: Vec::new(),
         }
     }
 
+    /// Takes all dependencies that should be resolved once the module of the graph is built.
+    pub(crate) fn take_deferred(&mut self) -> Vec<Arc<dyn Deferred>> {
+        std::mem::take(&mut self.deferred)
+    }
+
     pub fn filter_by(&self, set: std::collections::HashSet<TypeId>) -> Self {
         let mut new = self.clone();
         new.map.retain(|&k, _| set.contains(&k));
+        new.scoped.retain(|&k, _| set.contains(&k));
         new
     }
 
+    /// Retains only the named nodes with any of `names`.
+    pub(crate) fn filter_named(mut self, names: &[&'static str]) -> Self {
+        self.named.retain(|(_, name), _| names.contains(name));
+        self
+    }
+
     pub fn search_all<'a, T: 'static>(graphs: &'a [&Self]) -> Option<&'a T> {
         for graph in graphs {
             if let Some(ret) = graph.get_node::<T>() {
@@ -84,10 +690,213 @@ impl Graph {
         })
     }
 
+    /// Returns the node of type `T` named `name`.
+    pub fn get_named<T: 'static>(&self, name: &'static str) -> Option<&T> {
+        self.named
+            .get(&(TypeId::of::<T>(), name))
+            .and_then(|boxed| (&**boxed as &(dyn Any + Send + 'static)).downcast_ref())
+    }
+
+    pub fn contains_named<T: 'static>(&self, name: &'static str) -> bool {
+        self.named.contains_key(&(TypeId::of::<T>(), name))
+    }
+
+    /// Returns a clone of the node of type `T` named `name` in the graph or any of `imports`.
+    pub fn inject_named<T: Clone + 'static>(
+        &self,
+        name: &'static str,
+        imports: &[&Self],
+    ) -> Option<T> {
+        std::iter::once(self)
+            .chain(imports.iter().copied())
+            .find_map(|graph| graph.get_named::<T>(name))
+            .cloned()
+    }
+
+    /// Provides `t` under `name`, unless a node of type `T` with the same name is already
+    /// provided.
+    pub fn provide_named<T: Send + Sync + 'static>(&mut self, name: &'static str, t: Arc<T>) {
+        self.named.entry((TypeId::of::<T>(), name)).or_insert(t);
+    }
+
+    /// Adds `instance` to the implementations of the interface `I` that are injected as
+    /// `Vec<Arc<I>>`.
+    pub fn contribute<I: ?Sized + Send + Sync + 'static>(&mut self, instance: Arc<I>) {
+        let mut instances = self.get_contributions::<I>();
+        instances.push(instance);
+        self.multi
+            .insert(TypeId::of::<Vec<Arc<I>>>(), Arc::new(instances));
+    }
+
+    /// Declares the interface `I` as multi-bound, so that `Vec<Arc<I>>` is injected even if no
+    /// implementation is contributed to it.
+    pub fn declare_multi<I: ?Sized + Send + Sync + 'static>(&mut self) {
+        self.multi
+            .entry(TypeId::of::<Vec<Arc<I>>>())
+            .or_insert_with(|| Arc::new(Vec::<Arc<I>>::new()));
+    }
+
+    /// Returns all implementations contributed to the interface `I`.
+    pub fn get_contributions<I: ?Sized + Send + Sync + 'static>(&self) -> Vec<Arc<I>> {
+        self.multi
+            .get(&TypeId::of::<Vec<Arc<I>>>())
+            .and_then(|contributions| contributions.as_any().downcast_ref::<Vec<Arc<I>>>())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Adds all nodes of `other` that are not provided by the graph.
+    pub(crate) fn merge(&mut self, other: &Self) {
+        for (type_id, node) in &other.map {
+            self.map.entry(*type_id).or_insert_with(|| node.clone());
+        }
+        for (key, node) in &other.named {
+            self.named.entry(*key).or_insert_with(|| node.clone());
+        }
+        for (type_id, provider) in &other.scoped {
+            self.scoped
+                .entry(*type_id)
+                .or_insert_with(|| provider.clone());
+        }
+        self.merge_contributions(&[other]);
+    }
+
+    /// Adds the contributions of `imports` to those of the graph, ignoring the ones already
+    /// contributed, e.g. by a module imported by more than one of `imports`.
+    pub(crate) fn merge_contributions(&mut self, imports: &[&Self]) {
+        for graph in imports {
+            for (type_id, contributions) in &graph.multi {
+                let merged = match self.multi.get(type_id) {
+                    Some(own) => own.merge(&**contributions),
+                    None => contributions.clone(),
+                };
+                self.multi.insert(*type_id, merged);
+            }
+        }
+    }
+
     pub fn contains<T: 'static>(&self) -> bool {
         self.map.get(&TypeId::of::<T>()).is_some()
     }
 
+    /// Returns the scoped provider of `type_id` in the graph or any of `imports`.
+    fn scoped_provider<'a>(
+        &'a self,
+        type_id: TypeId,
+        imports: &[&'a Self],
+    ) -> Option<&'a Arc<dyn ScopedProvider>> {
+        std::iter::once(self)
+            .chain(imports.iter().copied())
+            .find_map(|graph| graph.scoped.get(&type_id))
+    }
+
+    /// Returns the scope of the provider of `type_id` in the graph or any of `imports`.
+    pub(crate) fn scope_of(&self, type_id: TypeId, imports: &[&Self]) -> Option<Scope> {
+        if self.map.contains_key(&type_id) || imports.iter().any(|x| x.map.contains_key(&type_id)) {
+            return Some(Scope::Singleton);
+        }
+        self.scoped_provider(type_id, imports)
+            .map(|provider| provider.scope())
+    }
+
+    /// Whether instances of `type_id` depend on the current request, i.e. whether its provider in
+    /// the graph or any of `imports` is request-scoped or transient with dependencies that depend
+    /// on the current request.
+    pub(crate) fn is_request_scoped(&self, type_id: TypeId, imports: &[&Self]) -> bool {
+        let mut pending = vec![type_id];
+        let mut visited = HashSet::new();
+        while let Some(type_id) = pending.pop() {
+            if !visited.insert(type_id) {
+                continue;
+            }
+            match self.scope_of(type_id, imports) {
+                Some(Scope::Request) => return true,
+                Some(Scope::Transient) => {
+                    let provider = self
+                        .scoped_provider(type_id, imports)
+                        .expect("Transient nodes have a scoped provider");
+                    pending.extend(
+                        provider
+                            .dependencies()
+                            .into_iter()
+                            .filter(|dependency| dependency.name.is_none())
+                            .map(|dependency| dependency.type_id),
+                    );
+                }
+                _ => {}
+            }
+        }
+        false
+    }
+
+    /// Returns an instance of `T` for injection, either a clone of a node in the graph or any of
+    /// `imports`, or a new instance created by a scoped provider. Returns `None` if `T` is not
+    /// provided.
+    pub fn inject<T: Clone + 'static>(
+        &mut self,
+        imports: &[&Self],
+    ) -> Result<Option<T>, ResolutionError> {
+        if let Some(node) = self.get_node::<T>().or_else(|| Self::search_all(imports)) {
+            return Ok(Some(node.clone()));
+        }
+        let type_id = TypeId::of::<T>();
+        if let Some(contributions) = std::iter::once(&*self)
+            .chain(imports.iter().copied())
+            .find_map(|graph| graph.multi.get(&type_id))
+        {
+            return Ok(contributions.as_any().downcast_ref::<T>().cloned());
+        }
+        let provider = self.scoped_provider(type_id, imports).cloned();
+        match provider {
+            Some(provider) => {
+                if provider.scope() == Scope::Request && !crate::module::in_request() {
+                    return Err(ResolutionError::InvalidScope {
+                        type_name: std::any::type_name::<T>(),
+                        module: None,
+                    });
+                }
+                let instance = provider.create(self, imports)?;
+                Ok((&*instance as &(dyn Any + Send + 'static))
+                    .downcast_ref::<T>()
+                    .cloned())
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns an instance of `T` for injection if it is provided, otherwise resolves a new
+    /// instance of `T` without storing it. Only used for guards, interceptors, exception filters
+    /// and pipes, which need not be provided; dependencies are injected with
+    /// [`inject`](#method.inject).
+    pub fn get_or_resolve<T: Injected<Output = T> + Clone + 'static>(
+        &mut self,
+        imports: &[&Self],
+    ) -> Result<T, ResolutionError> {
+        match self.inject::<T>(imports)? {
+            Some(node) => Ok(node),
+            None => T::resolve(self, imports),
+        }
+    }
+
+    /// Registers a provider that creates instances of `T` on demand.
+    pub(crate) fn provide_scoped<T: 'static>(&mut self, provider: Arc<dyn ScopedProvider>) {
+        self.scoped.entry(TypeId::of::<T>()).or_insert(provider);
+    }
+
+    /// Copies the nodes of `overrides` with any of `type_ids`, so that they take precedence over
+    /// providers of the same types registered later.
+    pub(crate) fn override_from(
+        &mut self,
+        overrides: &Self,
+        type_ids: impl Iterator<Item = TypeId>,
+    ) {
+        for type_id in type_ids {
+            if let Some(node) = overrides.map.get(&type_id) {
+                self.map.insert(type_id, node.clone());
+            }
+        }
+    }
+
     pub fn provide<T: Send + Sync + 'static>(&mut self, t: Arc<T>) -> &T {
         let exists = self.contains::<T>();
         if !exists {
@@ -99,18 +908,18 @@ impl Graph {
     pub fn resolve<'a, T: Injected + Sync + Send + 'static>(
         &'a mut self,
         imports: &'a [&Self],
-    ) -> &'a T {
+    ) -> Result<&'a T, ResolutionError> {
         let exists = self.contains::<T>();
         for graph in imports {
             let exists = graph.contains::<T>();
             if exists {
-                return graph.get_node::<T>().unwrap();
+                return Ok(graph.get_node::<T>().unwrap());
             }
         }
         if !exists {
-            let new = T::resolve(self, imports);
+            let new = T::resolve(self, imports)?;
             self.map.insert(TypeId::of::<T>(), Arc::new(new));
         }
-        self.get_node::<T>().unwrap()
+        Ok(self.get_node::<T>().unwrap())
     }
 }
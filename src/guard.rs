@@ -0,0 +1,256 @@
+//! Guards authorizing requests to controllers
+//!
+//! A guard is an injectable provider implementing [`CanActivate`]. Guards are attached to a
+//! controller, or to single routes of it, with `#[use_guards(...)]` and are run in order before
+//! the route handler. Unlike `#[get(guard(...))]`, which makes a route not match and results in
+//! `404 Not Found`, a guard rejects a request with a response of its own, e.g.
+//! `403 Forbidden`.
+//!
+//! Guards are resolved from the module of the controller when the module is built, so they may
+//! depend on any provider reachable from it.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use actix_web::dev::ServiceRequest;
+//! use actix_web::HttpResponse;
+//! use contraband::guard::{async_trait, CanActivate};
+//! use contraband::{controller, Injectable};
+//!
+//! #[derive(Clone, Injectable)]
+//! struct AuthGuard;
+//!
+//! #[async_trait(?Send)]
+//! impl CanActivate for AuthGuard {
+//!     async fn can_activate(&self, req: &ServiceRequest) -> Result<(), HttpResponse> {
+//!         match req.headers().get("Authorization") {
+//!             Some(_) => Ok(()),
+//!             None => Err(HttpResponse::Forbidden().finish()),
+//!         }
+//!     }
+//! }
+//!
+//! #[derive(Clone, Injectable)]
+//! struct AdminController;
+//!
+//! #[controller("/admin")]
+//! #[use_guards(AuthGuard)]
+//! impl AdminController {
+//!     #[get]
+//!     async fn dashboard(self) -> HttpResponse {
+//!         HttpResponse::Ok().finish()
+//!     }
+//! }
+//! ```
+use crate::module::ControllerProvider;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpResponse};
+use futures_util::future::{ok, FutureExt, LocalBoxFuture, Ready};
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+pub use async_trait::async_trait;
+
+/// Decides whether a request may be handled by a route.
+#[async_trait(?Send)]
+pub trait CanActivate: Send + Sync {
+    /// Returns `Ok(())` if the request may be handled, or the response the request is rejected
+    /// with.
+    ///
+    /// Guards are passed the [`ServiceRequest`] rather than an `HttpRequest`, which the route
+    /// handler must own once the guards are done and thus cannot be retained.
+    async fn can_activate(&self, req: &ServiceRequest) -> Result<(), HttpResponse>;
+}
+
+/// Middleware running the guards of a route. Generated by the
+/// [controller](../attr.controller.html) macro for `#[use_guards(...)]`.
+#[doc(hidden)]
+pub struct UseGuards {
+    guards: Rc<Vec<Arc<dyn CanActivate>>>,
+}
+
+impl UseGuards {
+    /// Guards a route of the controller `T` with its guards of `type_ids`.
+    pub fn of<T: 'static>(provider: &ControllerProvider<T>, type_ids: &[TypeId]) -> Self {
+        Self {
+            guards: Rc::new(provider.enhancers.guards.select(type_ids).collect()),
+        }
+    }
+}
+
+impl<S> Transform<S> for UseGuards
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = UseGuardsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(UseGuardsMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            guards: self.guards.clone(),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct UseGuardsMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    guards: Rc<Vec<Arc<dyn CanActivate>>>,
+}
+
+impl<S> Service for UseGuardsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let guards = self.guards.clone();
+        async move {
+            for guard in guards.iter() {
+                if let Err(response) = guard.can_activate(&req).await {
+                    return Ok(req.into_response(response));
+                }
+            }
+            let fut = service.borrow_mut().call(req);
+            fut.await
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as contraband;
+    use crate::core::ContrabandApp;
+    use crate::graph::Value;
+    use crate::module::{Module, ModuleFactory};
+    use crate::{controller, Injectable};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use once_cell::sync::Lazy;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Injectable)]
+    struct TokenGuard {
+        token: Value<&'static str>,
+    }
+
+    #[async_trait(?Send)]
+    impl CanActivate for TokenGuard {
+        async fn can_activate(&self, req: &ServiceRequest) -> Result<(), HttpResponse> {
+            match req.headers().get("Authorization") {
+                Some(token) if token == *self.token => Ok(()),
+                _ => Err(HttpResponse::Forbidden().finish()),
+            }
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct ReadOnlyGuard;
+
+    #[async_trait(?Send)]
+    impl CanActivate for ReadOnlyGuard {
+        async fn can_activate(&self, _: &ServiceRequest) -> Result<(), HttpResponse> {
+            Err(HttpResponse::MethodNotAllowed().finish())
+        }
+    }
+
+    static AUDITED: Lazy<Mutex<Vec<String>>> = Lazy::new(Default::default);
+
+    #[derive(Clone, Injectable)]
+    struct AuditGuard;
+
+    #[async_trait(?Send)]
+    impl CanActivate for AuditGuard {
+        async fn can_activate(&self, req: &ServiceRequest) -> Result<(), HttpResponse> {
+            let entry = format!("{} {}", req.method(), req.path());
+            AUDITED.lock().unwrap().push(entry);
+            Ok(())
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct AdminController;
+
+    #[controller("/admin")]
+    #[use_guards(TokenGuard)]
+    impl AdminController {
+        #[get]
+        async fn dashboard(self) -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+
+        #[post]
+        #[use_guards(ReadOnlyGuard)]
+        async fn update(self) -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct AuditedController;
+
+    #[controller("/audited")]
+    #[use_guards(AuditGuard)]
+    impl AuditedController {
+        #[get]
+        async fn index(self) -> HttpResponse {
+            HttpResponse::Ok().finish()
+        }
+    }
+
+    struct AppModule;
+    impl ModuleFactory for AppModule {
+        fn get_module() -> Module {
+            Module::new()
+                .provide_value(Value("secret"))
+                .controller::<AdminController>()
+                .controller::<AuditedController>()
+        }
+    }
+
+    #[contraband::test]
+    async fn test_guards_reject_requests() {
+        let mut server = ContrabandApp::new().test_server::<AppModule>().await;
+        let request = |req: test::TestRequest| req.uri("/admin").to_request();
+
+        let res = test::call_service(&mut server, request(test::TestRequest::get())).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get().header("Authorization", "secret");
+        let res = test::call_service(&mut server, request(req)).await;
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let req = test::TestRequest::post().header("Authorization", "secret");
+        let res = test::call_service(&mut server, request(req)).await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[contraband::test]
+    async fn test_guards_do_not_own_the_request() {
+        let mut server = ContrabandApp::new().test_server::<AppModule>().await;
+
+        let req = test::TestRequest::get().uri("/audited").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(*AUDITED.lock().unwrap(), ["GET /audited"]);
+    }
+}
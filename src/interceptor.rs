@@ -0,0 +1,375 @@
+//! Interceptors around route handlers
+//!
+//! An interceptor is an injectable provider implementing [`Interceptor`], e.g. in order to time
+//! requests, map responses or serve cached responses. Interceptors are attached to a controller,
+//! or to single routes of it, with `#[use_interceptors(...)]`, or to all routes of an application
+//! with [`ContrabandApp::use_interceptor`](crate::core::ContrabandApp::use_interceptor).
+//!
+//! The `before` hooks of the interceptors are called in order before the handler, the `after`
+//! hooks in reverse order once the handler has returned. Global interceptors come first, followed
+//! by those of the controller and those of the route. All interceptors of a route, including the
+//! global ones, run after its [guards](crate::guard), and requests not matching any route are not
+//! intercepted.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use actix_web::{HttpRequest, HttpResponse};
+//! use contraband::interceptor::{async_trait, Interceptor};
+//! use contraband::log::Logger;
+//! use contraband::{controller, Injectable};
+//!
+//! #[derive(Clone, Injectable)]
+//! struct LoggingInterceptor {
+//!     logger: Logger,
+//! }
+//!
+//! #[async_trait(?Send)]
+//! impl Interceptor for LoggingInterceptor {
+//!     async fn after(&self, req: &HttpRequest, res: HttpResponse) -> HttpResponse {
+//!         self.logger.info(format!("{} {} {}", req.method(), req.path(), res.status()));
+//!         res
+//!     }
+//! }
+//!
+//! #[derive(Clone, Injectable)]
+//! struct HelloController;
+//!
+//! #[controller]
+//! #[use_interceptors(LoggingInterceptor)]
+//! impl HelloController {
+//!     #[get]
+//!     async fn hello_world(self) -> HttpResponse {
+//!         HttpResponse::Ok().body("Hello world!")
+//!     }
+//! }
+//! ```
+use crate::graph::{Graph, Injected, ResolutionError};
+use crate::module::ControllerProvider;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{Error, HttpRequest, HttpResponse};
+use futures_util::future::{ok, FutureExt, LocalBoxFuture, Ready};
+use once_cell::sync::OnceCell;
+use std::any::TypeId;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+pub use async_trait::async_trait;
+
+/// Hooks called around a route handler.
+#[async_trait(?Send)]
+pub trait Interceptor: Send + Sync {
+    /// Called before the handler. Returning a response skips the handler, the remaining
+    /// interceptors and the `after` hook of this interceptor.
+    ///
+    /// Like [guards](crate::guard::CanActivate), `before` is passed the [`ServiceRequest`], which
+    /// is owned by the handler afterwards.
+    async fn before(&self, _req: &ServiceRequest) -> Option<HttpResponse> {
+        None
+    }
+
+    /// Called with the response of the handler, which may be mapped to another response.
+    async fn after(&self, _req: &HttpRequest, res: HttpResponse) -> HttpResponse {
+        res
+    }
+}
+
+/// Resolves the global interceptor `I` from the root module.
+pub(crate) fn resolve<I>(
+    graph: &mut Graph,
+    imports: &[&Graph],
+) -> Result<Arc<dyn Interceptor>, ResolutionError>
+where
+    I: Interceptor + Injected<Output = I> + 'static,
+{
+    Ok(graph.get_or_resolve::<Arc<I>>(imports)?)
+}
+
+/// The global interceptors of an application, which are resolved once the root module has been
+/// built and thus after the controllers.
+pub(crate) type GlobalInterceptors = Arc<OnceCell<Vec<Arc<dyn Interceptor>>>>;
+
+/// Middleware calling the hooks of interceptors around the wrapped service. Generated by the
+/// [controller](../attr.controller.html) macro for every route.
+#[doc(hidden)]
+pub struct UseInterceptors {
+    global: GlobalInterceptors,
+    interceptors: Rc<Vec<Arc<dyn Interceptor>>>,
+}
+
+impl UseInterceptors {
+    /// Intercepts a route of the controller `T` with the global interceptors, followed by its
+    /// interceptors of `type_ids`.
+    pub fn of<T: 'static>(provider: &ControllerProvider<T>, type_ids: &[TypeId]) -> Self {
+        Self {
+            global: provider.global_interceptors.clone(),
+            interceptors: Rc::new(provider.enhancers.interceptors.select(type_ids).collect()),
+        }
+    }
+}
+
+impl<S> Transform<S> for UseInterceptors
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type InitError = ();
+    type Transform = UseInterceptorsMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ok(UseInterceptorsMiddleware {
+            service: Rc::new(RefCell::new(service)),
+            global: self.global.clone(),
+            interceptors: self.interceptors.clone(),
+        })
+    }
+}
+
+#[doc(hidden)]
+pub struct UseInterceptorsMiddleware<S> {
+    service: Rc<RefCell<S>>,
+    global: GlobalInterceptors,
+    interceptors: Rc<Vec<Arc<dyn Interceptor>>>,
+}
+
+impl<S> Service for UseInterceptorsMiddleware<S>
+where
+    S: Service<Request = ServiceRequest, Response = ServiceResponse, Error = Error> + 'static,
+{
+    type Request = ServiceRequest;
+    type Response = ServiceResponse;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.borrow_mut().poll_ready(cx)
+    }
+
+    fn call(&mut self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+        let interceptors: Vec<_> = self
+            .global
+            .get()
+            .into_iter()
+            .flatten()
+            .chain(self.interceptors.iter())
+            .cloned()
+            .collect();
+        async move {
+            let mut entered = 0;
+            let mut intercepted = None;
+            for interceptor in &interceptors {
+                if let Some(res) = interceptor.before(&req).await {
+                    intercepted = Some(res);
+                    break;
+                }
+                entered += 1;
+            }
+            let res = match intercepted {
+                Some(res) => req.into_response(res),
+                None => {
+                    let fut = service.borrow_mut().call(req);
+                    fut.await?
+                }
+            };
+            let http_req = res.request().clone();
+            let mut res: HttpResponse = res.into();
+            for interceptor in interceptors[..entered].iter().rev() {
+                res = interceptor.after(&http_req, res).await;
+            }
+            Ok(ServiceResponse::new(http_req, res))
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as contraband;
+    use crate::core::ContrabandApp;
+    use crate::graph::Value;
+    use crate::guard::CanActivate;
+    use crate::module::{Module, ModuleFactory};
+    use crate::{controller, Injectable};
+    use actix_web::http::header::{HeaderName, HeaderValue};
+    use actix_web::http::StatusCode;
+    use actix_web::test;
+    use once_cell::sync::Lazy;
+    use std::sync::Mutex;
+
+    /// Appends its name to the `x-trace` header of the response.
+    #[derive(Clone, Injectable)]
+    struct TraceInterceptor {
+        name: Value<&'static str>,
+    }
+
+    #[async_trait(?Send)]
+    impl Interceptor for TraceInterceptor {
+        async fn after(&self, _: &HttpRequest, mut res: HttpResponse) -> HttpResponse {
+            let trace = match res.headers().get("x-trace") {
+                Some(trace) => format!("{},{}", trace.to_str().unwrap(), *self.name),
+                None => self.name.to_string(),
+            };
+            res.headers_mut().insert(
+                HeaderName::from_static("x-trace"),
+                HeaderValue::from_str(&trace).unwrap(),
+            );
+            res
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct UppercaseInterceptor;
+
+    #[async_trait(?Send)]
+    impl Interceptor for UppercaseInterceptor {
+        async fn after(&self, _: &HttpRequest, mut res: HttpResponse) -> HttpResponse {
+            res.headers_mut().insert(
+                HeaderName::from_static("x-trace"),
+                HeaderValue::from_static("UPPERCASE"),
+            );
+            res
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct CacheInterceptor;
+
+    #[async_trait(?Send)]
+    impl Interceptor for CacheInterceptor {
+        async fn before(&self, _: &ServiceRequest) -> Option<HttpResponse> {
+            Some(HttpResponse::Ok().body("cached"))
+        }
+    }
+
+    static LOGGED: Lazy<Mutex<Vec<String>>> = Lazy::new(Default::default);
+
+    #[derive(Clone, Injectable)]
+    struct LogInterceptor;
+
+    #[async_trait(?Send)]
+    impl Interceptor for LogInterceptor {
+        async fn before(&self, req: &ServiceRequest) -> Option<HttpResponse> {
+            let entry = format!("{} {}", req.method(), req.path());
+            LOGGED.lock().unwrap().push(entry);
+            None
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct BookController;
+
+    #[controller("/books")]
+    #[use_interceptors(UppercaseInterceptor)]
+    impl BookController {
+        #[get]
+        async fn list(self) -> HttpResponse {
+            HttpResponse::Ok().body("books")
+        }
+
+        #[get("/cached")]
+        #[use_interceptors(CacheInterceptor)]
+        async fn cached(self) -> HttpResponse {
+            HttpResponse::Ok().body("books")
+        }
+    }
+
+    struct AppModule;
+    impl ModuleFactory for AppModule {
+        fn get_module() -> Module {
+            Module::new()
+                .provide_value(Value("global"))
+                .controller::<BookController>()
+        }
+    }
+
+    #[contraband::test]
+    async fn test_interceptors_wrap_handlers() {
+        let mut server = ContrabandApp::new()
+            .use_interceptor::<TraceInterceptor>()
+            .test_server::<AppModule>()
+            .await;
+
+        let req = test::TestRequest::get().uri("/books").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.headers().get("x-trace").unwrap(), "UPPERCASE,global");
+        assert_eq!(test::read_body(res).await, "books");
+
+        let req = test::TestRequest::get().uri("/books/cached").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.headers().get("x-trace").unwrap(), "UPPERCASE,global");
+        assert_eq!(test::read_body(res).await, "cached");
+    }
+
+    struct LibraryModule;
+    impl ModuleFactory for LibraryModule {
+        fn get_module() -> Module {
+            Module::new().controller::<BookController>()
+        }
+    }
+
+    #[contraband::test]
+    async fn test_interceptors_do_not_own_the_request() {
+        let mut server = ContrabandApp::new()
+            .use_interceptor::<LogInterceptor>()
+            .test_server::<LibraryModule>()
+            .await;
+
+        let req = test::TestRequest::get().uri("/books").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "books");
+        assert_eq!(*LOGGED.lock().unwrap(), ["GET /books"]);
+    }
+
+    #[derive(Clone, Injectable)]
+    struct DenyGuard;
+
+    #[async_trait(?Send)]
+    impl CanActivate for DenyGuard {
+        async fn can_activate(&self, _: &ServiceRequest) -> Result<(), HttpResponse> {
+            Err(HttpResponse::Forbidden().finish())
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct AdminController;
+
+    #[controller("/admin")]
+    #[use_guards(DenyGuard)]
+    impl AdminController {
+        #[get]
+        async fn dashboard(self) -> HttpResponse {
+            HttpResponse::Ok().body("dashboard")
+        }
+    }
+
+    struct AdminModule;
+    impl ModuleFactory for AdminModule {
+        fn get_module() -> Module {
+            Module::new().controller::<AdminController>()
+        }
+    }
+
+    #[contraband::test]
+    async fn test_global_interceptors_run_after_guards() {
+        let mut server = ContrabandApp::new()
+            .use_interceptor::<CacheInterceptor>()
+            .test_server::<AdminModule>()
+            .await;
+
+        let req = test::TestRequest::get().uri("/admin").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+        let req = test::TestRequest::get().uri("/unknown").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -41,10 +41,17 @@
 //! * [Examples](https://github.com/styren/contraband/tree/master/examples)
 pub mod config;
 pub mod core;
+pub mod describe;
+pub mod exception;
 #[doc(hidden)]
 pub mod graph;
+pub mod guard;
+pub mod interceptor;
+pub mod lifecycle;
 pub mod log;
 pub mod module;
+pub mod pipe;
+pub mod testing;
 
 extern crate actix_rt;
 extern crate contraband_codegen;
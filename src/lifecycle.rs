@@ -0,0 +1,173 @@
+//! Lifecycle hooks for providers
+//!
+//! Providers can hook into the lifecycle of the application by implementing [`OnModuleInit`],
+//! [`OnApplicationBootstrap`] or [`OnApplicationShutdown`]. Implemented hooks are discovered when
+//! a provider derives `Injectable` and are called in dependency order, with shutdown hooks being
+//! called in reverse order.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use contraband::lifecycle::{async_trait, OnApplicationShutdown};
+//! use contraband::Injectable;
+//!
+//! #[derive(Clone, Injectable)]
+//! struct EventQueue;
+//!
+//! #[async_trait(?Send)]
+//! impl OnApplicationShutdown for EventQueue {
+//!     async fn on_application_shutdown(&self) {
+//!         // flush pending events
+//!     }
+//! }
+//! ```
+use futures_util::future::{FutureExt, LocalBoxFuture};
+use std::sync::Arc;
+
+pub use async_trait::async_trait;
+
+/// Called once the module of the provider has been built.
+#[async_trait(?Send)]
+pub trait OnModuleInit {
+    async fn on_module_init(&self);
+}
+
+/// Called once all modules have been built, before the server starts accepting connections.
+#[async_trait(?Send)]
+pub trait OnApplicationBootstrap {
+    async fn on_application_bootstrap(&self);
+}
+
+/// Called after the server has stopped, e.g. when receiving `SIGTERM` or `SIGINT`.
+#[async_trait(?Send)]
+pub trait OnApplicationShutdown {
+    async fn on_application_shutdown(&self);
+}
+
+type Hook = Box<dyn FnOnce() -> LocalBoxFuture<'static, ()>>;
+
+/// The lifecycle hooks implemented by a provider.
+#[derive(Default)]
+pub struct Hooks {
+    on_module_init: Option<Hook>,
+    on_application_bootstrap: Option<Hook>,
+    on_application_shutdown: Option<Hook>,
+}
+
+impl Hooks {
+    #[doc(hidden)]
+    pub fn new(
+        on_module_init: Option<Hook>,
+        on_application_bootstrap: Option<Hook>,
+        on_application_shutdown: Option<Hook>,
+    ) -> Self {
+        Self {
+            on_module_init,
+            on_application_bootstrap,
+            on_application_shutdown,
+        }
+    }
+}
+
+/// Used by the `Injectable` derive to discover which hooks a provider implements.
+///
+/// Hooks are probed through auto-ref specialization: the hook traits are implemented for
+/// `&Probe<T>` if `T` implements the hook, while [`NoHooks`] is implemented for all `Probe<T>`.
+#[doc(hidden)]
+pub struct Probe<T>(pub Arc<T>);
+
+#[doc(hidden)]
+pub trait NoHooks {
+    fn on_module_init_hook(&self) -> Option<Hook> {
+        None
+    }
+
+    fn on_application_bootstrap_hook(&self) -> Option<Hook> {
+        None
+    }
+
+    fn on_application_shutdown_hook(&self) -> Option<Hook> {
+        None
+    }
+}
+
+impl<T> NoHooks for Probe<T> {}
+
+#[doc(hidden)]
+pub trait ProbeOnModuleInit {
+    fn on_module_init_hook(&self) -> Option<Hook>;
+}
+
+impl<T: OnModuleInit + 'static> ProbeOnModuleInit for &Probe<T> {
+    fn on_module_init_hook(&self) -> Option<Hook> {
+        let instance = self.0.clone();
+        Some(Box::new(move || {
+            async move { instance.on_module_init().await }.boxed_local()
+        }))
+    }
+}
+
+#[doc(hidden)]
+pub trait ProbeOnApplicationBootstrap {
+    fn on_application_bootstrap_hook(&self) -> Option<Hook>;
+}
+
+impl<T: OnApplicationBootstrap + 'static> ProbeOnApplicationBootstrap for &Probe<T> {
+    fn on_application_bootstrap_hook(&self) -> Option<Hook> {
+        let instance = self.0.clone();
+        Some(Box::new(move || {
+            async move { instance.on_application_bootstrap().await }.boxed_local()
+        }))
+    }
+}
+
+#[doc(hidden)]
+pub trait ProbeOnApplicationShutdown {
+    fn on_application_shutdown_hook(&self) -> Option<Hook>;
+}
+
+impl<T: OnApplicationShutdown + 'static> ProbeOnApplicationShutdown for &Probe<T> {
+    fn on_application_shutdown_hook(&self) -> Option<Hook> {
+        let instance = self.0.clone();
+        Some(Box::new(move || {
+            async move { instance.on_application_shutdown().await }.boxed_local()
+        }))
+    }
+}
+
+/// The pending lifecycle hooks of an application, in the order the providers were resolved.
+#[derive(Default)]
+pub(crate) struct Lifecycle {
+    module_init: Vec<Hook>,
+    application_bootstrap: Vec<Hook>,
+    application_shutdown: Vec<Hook>,
+}
+
+impl Lifecycle {
+    pub(crate) fn register(&mut self, hooks: Hooks) {
+        self.module_init.extend(hooks.on_module_init);
+        self.application_bootstrap
+            .extend(hooks.on_application_bootstrap);
+        self.application_shutdown
+            .extend(hooks.on_application_shutdown);
+    }
+
+    /// Calls the module init hooks registered since the last call.
+    pub(crate) async fn init_module(&mut self) {
+        for hook in self.module_init.drain(..) {
+            hook().await;
+        }
+    }
+
+    pub(crate) async fn bootstrap(&mut self) {
+        for hook in self.application_bootstrap.drain(..) {
+            hook().await;
+        }
+    }
+
+    pub(crate) async fn shutdown(&mut self) {
+        while let Some(hook) = self.application_shutdown.pop() {
+            hook().await;
+        }
+    }
+}
@@ -1,5 +1,5 @@
 //! Global logging dependency
-use crate::graph::Graph;
+use crate::graph::{Graph, ResolutionError};
 use futures_util::future::{ok, Ready};
 use std::sync::Arc;
 
@@ -52,8 +52,8 @@ impl LoggingProvider for ConsoleLoggingProvider {
 
 impl crate::graph::Injected for Logger {
     type Output = Self;
-    fn resolve(_: &mut crate::graph::Graph, _: &[&Graph]) -> Self {
-        panic!("No logger provided.")
+    fn resolve(_: &mut crate::graph::Graph, _: &[&Graph]) -> Result<Self, ResolutionError> {
+        Err(ResolutionError::missing::<Self>())
     }
 }
 
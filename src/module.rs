@@ -2,61 +2,669 @@
 //!
 //! Note: For most use-cases it is recommended to use the [module](attr.module.html) macro to
 //! generate modules.
-use super::graph::{Graph, Injected};
-use actix_web::web::ServiceConfig;
-use std::any::TypeId;
+pub use super::graph::ResolutionError;
+use super::graph::{
+    resolution_order, Dependency, Graph, Injected, ProviderNode, ResolutionScope, ScopedProvider,
+};
+use crate::config::{validate_sections, Config, ConfigSection, SectionDeclaration};
+use crate::core::Profile;
+use crate::describe::{
+    ControllerDescription, ModuleDescription, ProviderDescription, RouteDescription,
+};
+use crate::exception::{self, Catch, ExceptionFilter, FilterFactory};
+use crate::guard::CanActivate;
+use crate::interceptor::{GlobalInterceptors, Interceptor};
+use crate::lifecycle::Lifecycle;
+use actix_web::http::{HeaderMap, Method, Uri};
+use actix_web::web::{self, ServiceConfig};
+use actix_web::HttpRequest;
+use futures_util::future::{ready, FutureExt, LocalBoxFuture};
+use once_cell::sync::OnceCell;
+use std::any::{Any, TypeId};
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::rc::Rc;
 use std::sync::Arc;
 
 pub trait ServiceFactory: Send + Sync {
     fn register(&self, app: &mut ServiceConfig);
 }
 
+/// Registers the routes of a controller. Implemented by the [controller](attr.controller.html)
+/// macro.
+pub trait ControllerFactory: Clone + Sized {
+    fn register(provider: ControllerProvider<Self>, app: &mut ServiceConfig);
+
+    /// The routes registered by the controller, see [`crate::describe`].
+    fn routes() -> Vec<RouteDescription> {
+        Vec::new()
+    }
+
+    /// Resolves the guards, interceptors, exception filters and pipes used by the controller and
+    /// its routes, see [`Enhancers`].
+    fn enhancers(_graph: &mut Graph, _imports: &[&Graph]) -> Result<Enhancers, ResolutionError> {
+        Ok(Enhancers::default())
+    }
+}
+
+/// Enhancers of the trait object `E`, keyed by the type of their provider.
+pub(crate) struct Registry<E: ?Sized>(HashMap<TypeId, Arc<E>>);
+
+impl<E: ?Sized> Registry<E> {
+    /// Resolves the enhancer `T`, either the instance provided to the module or a new instance,
+    /// and registers it as `E`.
+    fn insert<T>(
+        &mut self,
+        graph: &mut Graph,
+        imports: &[&Graph],
+        upcast: fn(Arc<T>) -> Arc<E>,
+    ) -> Result<(), ResolutionError>
+    where
+        T: Injected<Output = T> + Send + Sync + 'static,
+    {
+        let enhancer = graph.get_or_resolve::<Arc<T>>(imports)?;
+        self.0.insert(TypeId::of::<T>(), upcast(enhancer));
+        Ok(())
+    }
+
+    /// Returns the enhancer provided by `type_id`.
+    pub(crate) fn get(&self, type_id: &TypeId) -> &Arc<E> {
+        &self.0[type_id]
+    }
+
+    /// Returns the enhancers provided by `type_ids`, in order.
+    pub(crate) fn select<'a>(
+        &'a self,
+        type_ids: &'a [TypeId],
+    ) -> impl Iterator<Item = Arc<E>> + 'a {
+        type_ids
+            .iter()
+            .map(move |type_id| self.get(type_id).clone())
+    }
+}
+
+impl<E: ?Sized> Clone for Registry<E> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<E: ?Sized> Default for Registry<E> {
+    fn default() -> Self {
+        Self(HashMap::new())
+    }
+}
+
+/// The [guards](crate::guard), [interceptors](crate::interceptor),
+/// [exception filters](crate::exception) and [pipes](crate::pipe) used by a controller and its
+/// routes, resolved when its module is built.
+#[derive(Clone, Default)]
+pub struct Enhancers {
+    pub(crate) guards: Registry<dyn CanActivate>,
+    pub(crate) interceptors: Registry<dyn Interceptor>,
+    pub(crate) filters: Registry<dyn Catch>,
+    pub(crate) pipes: Registry<dyn Any + Send + Sync>,
+}
+
+impl Enhancers {
+    #[doc(hidden)]
+    pub fn guard<G>(&mut self, graph: &mut Graph, imports: &[&Graph]) -> Result<(), ResolutionError>
+    where
+        G: CanActivate + Injected<Output = G> + 'static,
+    {
+        self.guards.insert::<G>(graph, imports, |guard| guard)
+    }
+
+    #[doc(hidden)]
+    pub fn interceptor<I>(
+        &mut self,
+        graph: &mut Graph,
+        imports: &[&Graph],
+    ) -> Result<(), ResolutionError>
+    where
+        I: Interceptor + Injected<Output = I> + 'static,
+    {
+        self.interceptors
+            .insert::<I>(graph, imports, |interceptor| interceptor)
+    }
+
+    #[doc(hidden)]
+    pub fn filter<F>(
+        &mut self,
+        graph: &mut Graph,
+        imports: &[&Graph],
+    ) -> Result<(), ResolutionError>
+    where
+        F: ExceptionFilter + Injected<Output = F> + 'static,
+    {
+        self.filters.insert::<F>(graph, imports, |filter| filter)
+    }
+
+    #[doc(hidden)]
+    pub fn pipe<P>(&mut self, graph: &mut Graph, imports: &[&Graph]) -> Result<(), ResolutionError>
+    where
+        P: Injected<Output = P> + Send + Sync + 'static,
+    {
+        self.pipes.insert::<P>(graph, imports, |pipe| pipe)
+    }
+}
+
+/// An interface that can be bound to its implementation `T` with [`Module::bind`].
+///
+/// Implemented for trait objects by the [interface](../attr.interface.html) macro.
+pub trait Interface<T> {
+    /// Converts an instance of the implementation into the interface.
+    fn upcast(instance: Arc<T>) -> Arc<Self>;
+}
+
+/// The lifetime of a provider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scope {
+    /// A single instance is shared by all dependents. This is the default scope.
+    Singleton,
+    /// A new instance is created for each HTTP request and shared by all dependents within that
+    /// request. Request-scoped providers can only be injected into controllers and other
+    /// request-scoped providers, and may depend on the [`RequestContext`].
+    Request,
+    /// A new instance is created for each dependent.
+    Transient,
+}
+
+/// Data of the HTTP request currently being handled.
+///
+/// The request context can be injected into request-scoped providers, e.g. in order to read the
+/// authenticated user or a request id from the request headers.
+#[derive(Clone, Debug)]
+pub struct RequestContext {
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+}
+
+impl RequestContext {
+    fn new(req: &HttpRequest) -> Self {
+        Self {
+            method: req.method().clone(),
+            uri: req.uri().clone(),
+            headers: req.headers().clone(),
+        }
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns the value of header `name` if it is present and valid UTF-8.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|x| x.to_str().ok())
+    }
+}
+
+struct RequestState {
+    context: RequestContext,
+    instances: HashMap<TypeId, Arc<dyn Any + Send + Sync>>,
+}
+
+thread_local! {
+    static CURRENT_REQUEST: RefCell<Option<RequestState>> = const { RefCell::new(None) };
+}
+
+/// Whether request-scoped providers are currently being resolved for an HTTP request.
+pub(crate) fn in_request() -> bool {
+    CURRENT_REQUEST.with(|state| state.borrow().is_some())
+}
+
+/// Runs `f` with request-scoped providers resolving for `req`.
+fn with_request<R>(req: &HttpRequest, f: impl FnOnce() -> R) -> R {
+    struct Reset;
+    impl Drop for Reset {
+        fn drop(&mut self) {
+            CURRENT_REQUEST.with(|state| state.borrow_mut().take());
+        }
+    }
+
+    CURRENT_REQUEST.with(|state| {
+        *state.borrow_mut() = Some(RequestState {
+            context: RequestContext::new(req),
+            instances: HashMap::new(),
+        })
+    });
+    let _reset = Reset;
+    f()
+}
+
+/// Provides the [`RequestContext`] of the current request.
+#[derive(Debug)]
+pub(crate) struct RequestContextProvider;
+
+impl ScopedProvider for RequestContextProvider {
+    fn scope(&self) -> Scope {
+        Scope::Request
+    }
+
+    fn dependencies(&self) -> Vec<Dependency> {
+        Vec::new()
+    }
+
+    fn create(
+        &self,
+        _: &mut Graph,
+        _: &[&Graph],
+    ) -> Result<Arc<dyn Any + Send + Sync>, ResolutionError> {
+        CURRENT_REQUEST
+            .with(|state| state.borrow().as_ref().map(|x| x.context.clone()))
+            .map(|context| Arc::new(context) as Arc<dyn Any + Send + Sync>)
+            .ok_or(ResolutionError::InvalidScope {
+                type_name: std::any::type_name::<RequestContext>(),
+                module: None,
+            })
+    }
+}
+
+/// Creates request-scoped and transient instances of `T`.
+///
+/// Until the module of the provider has been built, instances are resolved from the graphs they
+/// are injected from. Afterwards they are resolved from the module of the provider.
+struct ScopedRecipe<T> {
+    scope: Scope,
+    resolution_scope: Arc<OnceCell<ResolutionScope>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T: Injected<Output = T> + 'static> ScopedProvider for ScopedRecipe<T> {
+    fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    fn dependencies(&self) -> Vec<Dependency> {
+        T::dependencies()
+    }
+
+    fn create(
+        &self,
+        graph: &mut Graph,
+        imported_graphs: &[&Graph],
+    ) -> Result<Arc<dyn Any + Send + Sync>, ResolutionError> {
+        let type_id = TypeId::of::<Arc<T>>();
+        if self.scope == Scope::Request {
+            let cached = CURRENT_REQUEST.with(|state| {
+                state
+                    .borrow()
+                    .as_ref()
+                    .and_then(|x| x.instances.get(&type_id).cloned())
+            });
+            if let Some(instance) = cached {
+                return Ok(instance);
+            }
+        }
+        let instance = match self.resolution_scope.get() {
+            Some(resolution_scope) => resolution_scope.resolve::<T>()?,
+            None => T::resolve(graph, imported_graphs)?,
+        };
+        let instance: Arc<dyn Any + Send + Sync> = Arc::new(Arc::new(instance));
+        if self.scope == Scope::Request {
+            CURRENT_REQUEST.with(|state| {
+                if let Some(state) = state.borrow_mut().as_mut() {
+                    state.instances.insert(type_id, instance.clone());
+                }
+            });
+        }
+        Ok(instance)
+    }
+}
+
+impl<T> fmt::Debug for ScopedRecipe<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScopedRecipe")
+            .field("type_name", &std::any::type_name::<T>())
+            .field("scope", &self.scope)
+            .finish()
+    }
+}
+
+/// Provides the instances of a controller to its route handlers.
+///
+/// Controllers depending on request-scoped providers are resolved for each request, other
+/// controllers are resolved once when their module is built.
+#[derive(Clone)]
+pub struct ControllerProvider<T> {
+    instance: ControllerInstance<T>,
+    pub(crate) enhancers: Enhancers,
+    /// The global interceptors of the application, run before those of the controller.
+    pub(crate) global_interceptors: GlobalInterceptors,
+    /// The exception filters of the module of the controller, tried after those of the
+    /// controller.
+    pub(crate) module_filters: Vec<Arc<dyn Catch>>,
+}
+
+impl<T: 'static> ControllerProvider<T> {
+    /// Returns the provider of the controller `T` handling `req`, e.g. in order to run its
+    /// enhancers.
+    pub(crate) fn of(req: &HttpRequest) -> &Self {
+        req.app_data::<web::Data<Self>>()
+            .expect("Enhancers are run within the scope of their controller")
+    }
+}
+
+#[derive(Clone)]
+enum ControllerInstance<T> {
+    Singleton(T),
+    Request(Arc<OnceCell<ResolutionScope>>),
+}
+
+impl<T: Injected<Output = T> + Clone + 'static> ControllerProvider<T> {
+    /// Returns the controller instance for handling `req`.
+    pub fn get(&self, req: &HttpRequest) -> Result<T, ResolutionError> {
+        match &self.instance {
+            ControllerInstance::Singleton(controller) => Ok(controller.clone()),
+            ControllerInstance::Request(resolution_scope) => with_request(req, || {
+                resolution_scope
+                    .get()
+                    .expect("Controller resolved before its module was built")
+                    .resolve::<T>()
+            }),
+        }
+    }
+}
+
+impl<T> ServiceFactory for ControllerProvider<T>
+where
+    T: ControllerFactory + Send + Sync,
+{
+    fn register(&self, app: &mut ServiceConfig) {
+        T::register(self.clone(), app);
+    }
+}
+
 pub(crate) struct Context {
     pub(crate) global_providers: Graph,
     pub(crate) modules: HashMap<TypeId, Arc<ResolvedModule>>,
+    /// The modules being built, keyed by their type and whether they are dynamic.
+    pub(crate) import_chain: Vec<((TypeId, bool), &'static str)>,
+    pub(crate) lifecycle: Lifecycle,
+    /// Configuration sections of the module tree that are validated once the settings are first
+    /// injected.
+    pub(crate) unvalidated_sections: Vec<SectionDeclaration>,
+    pub(crate) overrides: Overrides,
+    /// The global interceptors of the application, run around the routes of every controller.
+    pub(crate) interceptors: GlobalInterceptors,
+}
+
+impl Context {
+    pub(crate) fn new(global_providers: Graph) -> Self {
+        Self {
+            global_providers,
+            modules: HashMap::new(),
+            import_chain: Vec::new(),
+            lifecycle: Lifecycle::default(),
+            unvalidated_sections: Vec::new(),
+            overrides: Overrides::default(),
+            interceptors: GlobalInterceptors::default(),
+        }
+    }
+}
+
+/// A module identified by the factory it is created from.
+#[derive(Clone)]
+pub(crate) struct ModuleRef {
+    type_id: TypeId,
+    name: &'static str,
+    factory: Rc<dyn Fn() -> Module>,
+    /// Whether the module is created with parameters, in which case it is not shared between the
+    /// modules importing it.
+    dynamic: bool,
+}
+
+impl ModuleRef {
+    /// Identifies the module. Modules of the same type are the same module, except for dynamic
+    /// modules, each of which is created by a factory of its own.
+    fn key(&self) -> (TypeId, Option<usize>) {
+        let instance = if self.dynamic {
+            Some(&*self.factory as *const dyn Fn() -> Module as *const () as usize)
+        } else {
+            None
+        };
+        (self.type_id, instance)
+    }
+
+    pub(crate) fn of<T: ModuleFactory + 'static>() -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+            factory: Rc::new(T::get_module),
+            dynamic: false,
+        }
+    }
+}
+
+/// Probes whether `T` of `#[export(T)]` is a module, which is re-exported, or a provider.
+///
+/// Modules are probed through auto-ref specialization like lifecycle hooks:
+/// [`ExportModule`] is implemented for `&ExportProbe<T>` if `T` is a module, while
+/// [`ExportProvider`] is implemented for `ExportProbe<T>` of injectable `T`.
+#[doc(hidden)]
+pub struct ExportProbe<T>(pub PhantomData<T>);
+
+#[doc(hidden)]
+pub trait ExportModule {
+    fn export_to(&self, module: Module) -> Module;
+}
+
+impl<T: ModuleFactory + 'static> ExportModule for &ExportProbe<T> {
+    fn export_to(&self, module: Module) -> Module {
+        module.reexport::<T>()
+    }
+}
+
+#[doc(hidden)]
+pub trait ExportProvider {
+    fn export_to(&self, module: Module) -> Module;
+}
+
+impl<T: Injected + Send + Sync + 'static> ExportProvider for ExportProbe<T> {
+    fn export_to(&self, module: Module) -> Module {
+        module.export::<T>()
+    }
+}
+
+/// A module created with parameters, such as the options of a connection pool, rather than by
+/// [`ModuleFactory::get_module`]. Dynamic modules are imported with
+/// [`Module::import_dynamic`].
+///
+/// The factory of a dynamic module may be called more than once while the module tree is
+/// resolved. Unless the module is global, every import of a dynamic module builds a separate
+/// instance of it, even if a module of the same type has already been built.
+///
+/// A dynamic module may import the static module of its type, but not another dynamic module of
+/// its type, directly or transitively, which is reported as a [`ResolutionError::ImportCycle`].
+///
+/// ## Example
+///
+/// ```rust,no_run
+/// use contraband::graph::Value;
+/// use contraband::module::{DynamicModule, Module};
+///
+/// #[derive(Clone)]
+/// struct CacheOptions {
+///     capacity: usize,
+/// }
+///
+/// struct CacheModule;
+///
+/// impl CacheModule {
+///     fn for_root(options: CacheOptions) -> DynamicModule {
+///         DynamicModule::new::<Self>(move || {
+///             Module::new()
+///                 .export_value::<Value<CacheOptions>>()
+///                 .provide_value(Value(options.clone()))
+///         })
+///     }
+/// }
+///
+/// let module = Module::new().import_dynamic(CacheModule::for_root(CacheOptions { capacity: 64 }));
+/// ```
+pub struct DynamicModule(ModuleRef);
+
+impl DynamicModule {
+    /// Creates a dynamic module of type `T` whose module is created by `factory`. Module
+    /// overrides of `T` apply to the dynamic module as well.
+    pub fn new<T: 'static>(factory: impl Fn() -> Module + 'static) -> Self {
+        Self(ModuleRef {
+            type_id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+            factory: Rc::new(factory),
+            dynamic: true,
+        })
+    }
+}
+
+/// Providers and modules replacing those declared in the module tree, e.g. by mocks in tests.
+#[derive(Default)]
+pub(crate) struct Overrides {
+    /// Nodes that take precedence over the providers of the same type in any module.
+    pub(crate) providers: Graph,
+    /// Modules that are built instead of the modules they replace, keyed by the replaced module.
+    pub(crate) modules: HashMap<TypeId, ModuleRef>,
+}
+
+impl Overrides {
+    fn module(&self, module: ModuleRef) -> ModuleRef {
+        self.modules.get(&module.type_id).cloned().unwrap_or(module)
+    }
+}
+
+type BuildStep = Box<dyn FnOnce(&mut ResolvedModule, &mut Context) -> Result<(), ResolutionError>>;
+
+type AsyncBuildStep = Box<
+    dyn for<'a> FnOnce(
+        &'a mut ResolvedModule,
+        &'a mut Context,
+    ) -> LocalBoxFuture<'a, Result<(), ResolutionError>>,
+>;
+
+fn async_step<F>(step: F) -> AsyncBuildStep
+where
+    F: for<'a> FnOnce(
+            &'a mut ResolvedModule,
+            &'a mut Context,
+        ) -> LocalBoxFuture<'a, Result<(), ResolutionError>>
+        + 'static,
+{
+    Box::new(step)
 }
 
 #[derive(Default)]
 pub struct Module {
+    name: &'static str,
+    global: bool,
     exported_providers: HashSet<TypeId>,
+    exported_names: Vec<&'static str>,
+    reexported_modules: Vec<(TypeId, &'static str)>,
     entities: HashSet<TypeId>,
-    imports: Vec<Box<dyn FnOnce(&mut ResolvedModule, &mut Context)>>,
+    /// The entities of the conditional providers of `provider_nodes` at the same indices, which
+    /// are only entities of the module if the provider is declared for the active profile.
+    conditional_entities: HashMap<usize, Vec<TypeId>>,
+    /// The provider declared by each step of `providers`, at the same index.
+    provider_nodes: Vec<ProviderNode>,
+    config_sections: Vec<SectionDeclaration>,
+    imported_factories: Vec<ModuleRef>,
+    imports: Vec<AsyncBuildStep>,
     provider_values: Vec<Box<dyn FnOnce(&mut ResolvedModule)>>,
-    providers: Vec<Box<dyn FnOnce(&mut ResolvedModule, &mut Context)>>,
-    controllers: Vec<Box<dyn FnOnce(&mut ResolvedModule, &mut Context)>>,
+    providers: Vec<AsyncBuildStep>,
+    controllers: Vec<BuildStep>,
+    /// The type names of the exports, values and controllers, for [`describe`].
+    exported_type_names: Vec<&'static str>,
+    values: Vec<ProviderDescription>,
+    controller_descriptions: Vec<ControllerDescription>,
+    filters: Vec<FilterFactory>,
 }
 
 impl Module {
     pub fn new() -> Self {
         Self {
+            name: "<anonymous module>",
+            global: false,
             exported_providers: HashSet::new(),
+            exported_names: Vec::new(),
+            reexported_modules: Vec::new(),
             entities: HashSet::new(),
+            conditional_entities: HashMap::new(),
+            provider_nodes: Vec::new(),
+            config_sections: Vec::new(),
+            imported_factories: Vec::new(),
             imports: Vec::new(),
             provider_values: Vec::new(),
             providers: Vec::new(),
             controllers: Vec::new(),
+            exported_type_names: Vec::new(),
+            values: Vec::new(),
+            controller_descriptions: Vec::new(),
+            filters: Vec::new(),
         }
     }
 
-    pub fn import<T: ModuleFactory + 'static>(mut self) -> Self {
-        self.imports.push(Box::new(|module, ctx| {
-            if let Some(resolved_module) = ctx.modules.get(&TypeId::of::<T>()) {
-                module.imported_modules.push(resolved_module.clone());
-            } else {
-                let new_module = Arc::new(T::get_module().build(ctx));
-                ctx.modules.insert(TypeId::of::<T>(), new_module.clone());
-                module.imported_modules.push(new_module);
+    pub fn import<T: ModuleFactory + 'static>(self) -> Self {
+        self.import_ref(ModuleRef::of::<T>())
+    }
+
+    /// Imports a module created with parameters, see [`DynamicModule`].
+    pub fn import_dynamic(self, module: DynamicModule) -> Self {
+        self.import_ref(module.0)
+    }
+
+    fn import_ref(mut self, imported: ModuleRef) -> Self {
+        self.imported_factories.push(imported.clone());
+        self.imports.push(async_step(move |module, ctx| {
+            async move {
+                let type_id = imported.type_id;
+                let dynamic = imported.dynamic;
+                // Dynamic modules are built for every import, unless a module of their type has
+                // been built as a global module.
+                let cached = ctx
+                    .modules
+                    .get(&type_id)
+                    .filter(|resolved_module| !dynamic || resolved_module.global);
+                if let Some(resolved_module) = cached {
+                    module.imported_modules.push(resolved_module.clone());
+                } else {
+                    let new_module = Arc::new(build_module_ref(ctx, imported).await?);
+                    if !dynamic {
+                        ctx.modules.insert(type_id, new_module.clone());
+                    }
+                    module.imported_modules.push(new_module);
+                }
+                Ok(())
             }
+            .boxed_local()
         }));
         self
     }
 
+    /// Makes the module global, such that its exports are reachable from every module of the
+    /// application without importing it. A global module still needs to be imported once, e.g.
+    /// by the root module.
+    pub fn global(mut self) -> Self {
+        self.global = true;
+        self
+    }
+
     pub fn export<T>(mut self) -> Self
     where
         T: Injected + Send + Sync + 'static,
     {
         self.exported_providers.insert(TypeId::of::<Arc<T>>());
+        self.exported_type_names.push(std::any::type_name::<T>());
         self
     }
 
@@ -65,6 +673,44 @@ impl Module {
         T: Injected + Send + Sync + 'static,
     {
         self.exported_providers.insert(TypeId::of::<T>());
+        self.exported_type_names.push(std::any::type_name::<T>());
+        self
+    }
+
+    /// Exports everything exported by the imported module `M`, e.g. in order to bundle several
+    /// modules in a facade module. `M` needs to be imported by the module as well.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use contraband::config::ConfigModule;
+    /// use contraband::module::{Module, ModuleFactory};
+    ///
+    /// struct InfrastructureModule;
+    ///
+    /// impl ModuleFactory for InfrastructureModule {
+    ///     fn get_module() -> Module {
+    ///         Module::new()
+    ///             .import::<ConfigModule>()
+    ///             .reexport::<ConfigModule>()
+    ///     }
+    /// }
+    /// ```
+    pub fn reexport<M: ModuleFactory + 'static>(mut self) -> Self {
+        self.reexported_modules
+            .push((TypeId::of::<M>(), std::any::type_name::<M>()));
+        self.exported_type_names.push(std::any::type_name::<M>());
+        self
+    }
+
+    /// Exports a value of type `T`, such as one provided by
+    /// [`provide_factory_async`](#method.provide_factory_async).
+    pub fn export_value<T>(mut self) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        self.exported_providers.insert(TypeId::of::<T>());
+        self.exported_type_names.push(std::any::type_name::<T>());
         self
     }
 
@@ -75,111 +721,1024 @@ impl Module {
         self.provider_values.push(Box::new(|module| {
             module.graph.provide(Arc::new(t));
         }));
+        self.values.push(ProviderDescription {
+            type_name: std::any::type_name::<T>(),
+            name: None,
+            dependencies: Vec::new(),
+        });
         self.entities.insert(TypeId::of::<T>());
         self
     }
 
-    pub fn provide<T>(mut self) -> Self
+    /// Provides `value` under `name`, which is injected into fields annotated with
+    /// `#[inject(name = "...")]`. Named values are independent of unnamed values of the same
+    /// type.
+    pub fn provide_value_named<T>(mut self, name: &'static str, value: T) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        self.provider_values.push(Box::new(move |module| {
+            module.graph.provide_named(name, Arc::new(value));
+        }));
+        self.values.push(ProviderDescription {
+            type_name: std::any::type_name::<T>(),
+            name: Some(name),
+            dependencies: Vec::new(),
+        });
+        self
+    }
+
+    /// Provides a new instance of `T` under `name`, which is injected as `Arc<T>` into fields
+    /// annotated with `#[inject(name = "...")]`.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use contraband::module::Module;
+    /// use contraband::Injectable;
+    /// use std::sync::Arc;
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct Database;
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct ReportService {
+    ///     #[inject(name = "replica")]
+    ///     database: Arc<Database>,
+    /// }
+    ///
+    /// let module = Module::new()
+    ///     .provide::<Database>()
+    ///     .provide_named::<Database>("replica")
+    ///     .provide::<ReportService>();
+    /// ```
+    pub fn provide_named<T>(mut self, name: &'static str) -> Self
     where
         T: Injected<Output = T> + 'static,
     {
-        self.providers.push(Box::new(|module, ctx| {
+        self.providers.push(async_step(move |module, ctx| {
+            if module.graph.contains_named::<Arc<T>>(name) {
+                return ready(Ok(())).boxed_local();
+            }
             let mut imported_graphs = vec![&ctx.global_providers];
             for module in &module.imported_modules {
                 imported_graphs.push(&module.exported_graph);
             }
-            module.graph.resolve::<Arc<T>>(&imported_graphs);
+            let resolved = T::resolve(&mut module.graph, &imported_graphs).map(Arc::new);
+            ready(resolved.map(|instance| {
+                ctx.lifecycle.register(T::hooks(&instance));
+                module.graph.provide_named(name, Arc::new(instance));
+            }))
+            .boxed_local()
         }));
-        self.entities.insert(TypeId::of::<T>());
+        self.provider_nodes.push(ProviderNode {
+            type_id: TypeId::of::<Arc<T>>(),
+            type_name: std::any::type_name::<T>(),
+            name: Some(name),
+            dependencies: T::dependencies(),
+            condition: None,
+        });
         self
     }
 
-    pub fn controller<T>(mut self) -> Self
+    /// Exports all providers named `name`.
+    pub fn export_named(mut self, name: &'static str) -> Self {
+        self.exported_names.push(name);
+        self
+    }
+
+    pub fn provide<T>(mut self) -> Self
     where
-        T: Injected<Output = T> + ServiceFactory + 'static,
+        T: Injected<Output = T> + 'static,
     {
-        self.controllers.push(Box::new(|module, ctx| {
+        self.providers.push(async_step(|module, ctx| {
             let mut imported_graphs = vec![&ctx.global_providers];
             for module in &module.imported_modules {
                 imported_graphs.push(&module.exported_graph);
             }
-            let resolved = T::resolve(&mut module.graph, &imported_graphs);
-            module.controllers.push(Arc::new(resolved));
+            // The instance of an imported provider is reused, whose hooks are already registered.
+            let created = !module.graph.contains::<Arc<T>>()
+                && !imported_graphs
+                    .iter()
+                    .any(|graph| graph.contains::<Arc<T>>());
+            let resolved = module
+                .graph
+                .resolve::<Arc<T>>(&imported_graphs)
+                .map(T::hooks);
+            ready(resolved.map(|hooks| {
+                if created {
+                    ctx.lifecycle.register(hooks);
+                }
+            }))
+            .boxed_local()
         }));
+        self.provider_nodes.push(ProviderNode {
+            type_id: TypeId::of::<Arc<T>>(),
+            type_name: std::any::type_name::<T>(),
+            name: None,
+            dependencies: T::dependencies(),
+            condition: None,
+        });
         self.entities.insert(TypeId::of::<T>());
         self
     }
 
-    pub(crate) fn build(self, ctx: &mut Context) -> ResolvedModule {
-        let mut module = ResolvedModule::new();
-        for import in self.imports {
-            import(&mut module, ctx);
-        }
-        for provided_value in self.provider_values {
-            provided_value(&mut module);
-        }
-        for provider in self.providers {
-            provider(&mut module, ctx);
-        }
-        for controller in self.controllers {
-            controller(&mut module, ctx);
-        }
-        module.exported_graph = module.graph.filter_by(self.exported_providers);
-        module
+    /// Provides a value of type `T` that is constructed asynchronously by `factory`.
+    ///
+    /// The dependencies `D` of the factory are resolved like those of any other provider, either
+    /// as a tuple of injectable types or as a struct deriving `Injectable`. The factory is
+    /// awaited while the module is built, so the value is available to all providers and
+    /// controllers registered after it. If the factory fails the module fails to build with
+    /// [`ResolutionError::FactoryFailed`].
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use contraband::graph::Value;
+    /// use contraband::log::Logger;
+    /// use contraband::module::Module;
+    ///
+    /// #[derive(Clone)]
+    /// struct HttpClient;
+    ///
+    /// let module = Module::new().provide_factory_async(|(logger,): (Logger,)| async move {
+    ///     logger.info("Connecting HTTP client".to_string());
+    ///     Ok::<_, std::io::Error>(Value(HttpClient))
+    /// });
+    /// ```
+    pub fn provide_factory_async<D, T, E, F, Fut>(self, factory: F) -> Self
+    where
+        D: Injected<Output = D> + Clone + 'static,
+        T: Send + Sync + 'static,
+        E: Display,
+        F: FnOnce(D) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+    {
+        self.factory_async(None, factory)
     }
-}
-
-#[derive(Clone)]
-pub(crate) struct ResolvedModule {
-    pub(crate) graph: Graph,
-    pub(crate) imported_modules: Vec<Arc<Self>>,
-    exported_graph: Graph,
-    pub(crate) controllers: Vec<Arc<dyn ServiceFactory>>,
-}
 
-impl ResolvedModule {
-    pub fn new() -> Self {
-        Self {
-            graph: Graph::new(),
-            exported_graph: Graph::new(),
-            imported_modules: Vec::new(),
-            controllers: Vec::new(),
-        }
+    /// Provides a value of type `T` named `name` that is constructed asynchronously by
+    /// `factory`, as with [`provide_factory_async`](#method.provide_factory_async).
+    pub fn provide_factory_async_named<D, T, E, F, Fut>(
+        self,
+        name: &'static str,
+        factory: F,
+    ) -> Self
+    where
+        D: Injected<Output = D> + Clone + 'static,
+        T: Send + Sync + 'static,
+        E: Display,
+        F: FnOnce(D) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+    {
+        self.factory_async(Some(name), factory)
     }
-}
 
-pub trait ModuleFactory: Sized {
-    fn get_module() -> Module;
-}
+    fn factory_async<D, T, E, F, Fut>(mut self, name: Option<&'static str>, factory: F) -> Self
+    where
+        D: Injected<Output = D> + Clone + 'static,
+        T: Send + Sync + 'static,
+        E: Display,
+        F: FnOnce(D) -> Fut + 'static,
+        Fut: Future<Output = Result<T, E>> + 'static,
+    {
+        self.providers.push(async_step(move |module, ctx| {
+            let provided = match name {
+                Some(name) => module.graph.contains_named::<T>(name),
+                None => module.graph.contains::<T>(),
+            };
+            if provided {
+                // The value is overridden, so the factory is not run.
+                return ready(Ok(())).boxed_local();
+            }
+            let mut imported_graphs = vec![&ctx.global_providers];
+            for module in &module.imported_modules {
+                imported_graphs.push(&module.exported_graph);
+            }
+            let dependencies = D::resolve(&mut module.graph, &imported_graphs).map_err(|err| {
+                match err {
+                    // Missing dependencies are reported as required by the value of the factory
+                    // rather than by the tuple of its arguments.
+                    ResolutionError::MissingProvider {
+                        type_name,
+                        name,
+                        required_by: Some(required_by),
+                        field,
+                        module,
+                    } if required_by == std::any::type_name::<D>() => {
+                        ResolutionError::MissingProvider {
+                            type_name,
+                            name,
+                            required_by: Some(std::any::type_name::<T>()),
+                            field,
+                            module,
+                        }
+                    }
+                    err => err,
+                }
+            });
+            async move {
+                let value =
+                    factory(dependencies?)
+                        .await
+                        .map_err(|err| ResolutionError::FactoryFailed {
+                            type_name: std::any::type_name::<T>(),
+                            message: err.to_string(),
+                            module: None,
+                        })?;
+                match name {
+                    Some(name) => module.graph.provide_named(name, Arc::new(value)),
+                    None => {
+                        module.graph.provide(Arc::new(value));
+                    }
+                }
+                Ok(())
+            }
+            .boxed_local()
+        }));
+        self.provider_nodes.push(ProviderNode {
+            type_id: TypeId::of::<T>(),
+            type_name: std::any::type_name::<T>(),
+            name,
+            dependencies: D::dependencies(),
+            condition: None,
+        });
+        self.entities.insert(TypeId::of::<T>());
+        self
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate as contraband;
-    use crate::graph::Value;
-    use crate::module::Module;
-    use crate::Injectable;
+    /// Provides the configuration section `T` as an injectable [`Config<T>`], deserialized from the
+    /// [`Settings`](crate::config::Settings) exported by an imported
+    /// [`ConfigModule`](crate::config::ConfigModule). If the section does not match `T` the module
+    /// fails to build with [`ResolutionError::InvalidConfig`].
+    ///
+    /// When the module is built by a `ContrabandApp` the first section to be injected validates
+    /// the sections of all modules in the tree, so that all invalid sections are reported
+    /// together.
+    pub fn config<T: ConfigSection>(mut self) -> Self {
+        self.providers.push(async_step(|module, ctx| {
+            let sections = std::mem::take(&mut ctx.unvalidated_sections);
+            let mut imported_graphs = vec![&ctx.global_providers];
+            for module in &module.imported_modules {
+                imported_graphs.push(&module.exported_graph);
+            }
+            let resolved = match validate_sections(&module.graph, &imported_graphs, &sections) {
+                Ok(()) => module
+                    .graph
+                    .resolve::<Config<T>>(&imported_graphs)
+                    .map(|_| ()),
+                Err(err) => Err(err),
+            };
+            ready(resolved).boxed_local()
+        }));
+        self.config_sections.push(SectionDeclaration::of::<T>());
+        self.provider_nodes.push(ProviderNode {
+            type_id: TypeId::of::<Config<T>>(),
+            type_name: std::any::type_name::<Config<T>>(),
+            name: None,
+            dependencies: Config::<T>::dependencies(),
+            condition: None,
+        });
+        self.entities.insert(TypeId::of::<Config<T>>());
+        self
+    }
 
-    fn get_empty_ctx() -> Context {
-        Context {
-            global_providers: Graph::new(),
-            modules: HashMap::new(),
+    /// Provides `T` with the lifetime of `scope`. Providing `T` with [`Scope::Singleton`] is
+    /// equivalent to [`provide`](#method.provide).
+    pub fn provide_scoped<T>(mut self, scope: Scope) -> Self
+    where
+        T: Injected<Output = T> + 'static,
+    {
+        if scope == Scope::Singleton {
+            return self.provide::<T>();
         }
+        self.providers.push(async_step(move |module, _| {
+            let resolution_scope = Arc::new(OnceCell::new());
+            module.unresolved_scopes.push(resolution_scope.clone());
+            module
+                .graph
+                .provide_scoped::<Arc<T>>(Arc::new(ScopedRecipe::<T> {
+                    scope,
+                    resolution_scope,
+                    _marker: PhantomData,
+                }));
+            ready(Ok(())).boxed_local()
+        }));
+        self.provider_nodes.push(ProviderNode {
+            type_id: TypeId::of::<Arc<T>>(),
+            type_name: std::any::type_name::<T>(),
+            name: None,
+            dependencies: T::dependencies(),
+            condition: None,
+        });
+        self.entities.insert(TypeId::of::<T>());
+        self
     }
 
-    #[test]
-    fn test_provide_value_get_resolved() {
-        let value = Value("test_str");
-        let mut ctx = get_empty_ctx();
-        let resolved = Module::new().provide_value(value).build(&mut ctx);
-        assert_eq!(
+    /// Provides `T` only if `condition` holds for the active [`Profile`] of the application.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use contraband::module::Module;
+    /// use contraband::Injectable;
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct InMemoryMailer;
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct SmtpMailer;
+    ///
+    /// let module = Module::new()
+    ///     .provide_if::<InMemoryMailer>(|profile| !profile.is("prod"))
+    ///     .provide_if::<SmtpMailer>(|profile| profile.is("prod"));
+    /// ```
+    pub fn provide_if<T>(self, condition: impl FnOnce(&Profile) -> bool + 'static) -> Self
+    where
+        T: Injected<Output = T> + 'static,
+    {
+        self.provide_scoped_if::<T>(Scope::Singleton, condition)
+    }
+
+    /// Provides `T` with the lifetime of `scope` only if `condition` holds for the active
+    /// [`Profile`] of the application.
+    pub fn provide_scoped_if<T>(
+        self,
+        scope: Scope,
+        condition: impl FnOnce(&Profile) -> bool + 'static,
+    ) -> Self
+    where
+        T: Injected<Output = T> + 'static,
+    {
+        self.only_if(condition, |module| module.provide_scoped::<T>(scope))
+    }
+
+    /// Provides an `Arc<I>` of the interface `I`, implemented by `T`. `T` is provided as well
+    /// unless it is already reachable from the module.
+    ///
+    /// Interfaces are trait objects of traits annotated with the
+    /// [interface](../attr.interface.html) macro.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use contraband::module::Module;
+    /// use contraband::{interface, Injectable};
+    /// use std::sync::Arc;
+    ///
+    /// #[interface]
+    /// trait BookRepository: Send + Sync {
+    ///     fn count(&self) -> usize;
+    /// }
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct SqlBookRepository;
+    ///
+    /// impl BookRepository for SqlBookRepository {
+    ///     fn count(&self) -> usize {
+    ///         0
+    ///     }
+    /// }
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct BookService {
+    ///     repository: Arc<dyn BookRepository>,
+    /// }
+    ///
+    /// let module = Module::new()
+    ///     .bind::<dyn BookRepository, SqlBookRepository>()
+    ///     .provide::<BookService>();
+    /// ```
+    pub fn bind<I, T>(mut self) -> Self
+    where
+        I: Interface<T> + ?Sized + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        self.providers.push(async_step(|module, ctx| {
+            if module.graph.contains::<Arc<I>>() {
+                // The binding is overridden.
+                return ready(Ok(())).boxed_local();
+            }
+            let mut imported_graphs = vec![&ctx.global_providers];
+            for module in &module.imported_modules {
+                imported_graphs.push(&module.exported_graph);
+            }
+            let provided = module.graph.contains::<Arc<T>>()
+                || imported_graphs
+                    .iter()
+                    .any(|graph| graph.contains::<Arc<T>>());
+            let resolved = match module.graph.resolve::<Arc<T>>(&imported_graphs) {
+                Ok(instance) => Ok(instance.clone()),
+                Err(err) => Err(err),
+            };
+            let bound = resolved.map(|instance| {
+                if !provided {
+                    ctx.lifecycle.register(T::hooks(&instance));
+                }
+                module.graph.provide(Arc::new(I::upcast(instance)));
+            });
+            ready(bound).boxed_local()
+        }));
+        self.provider_nodes.push(ProviderNode {
+            type_id: TypeId::of::<Arc<I>>(),
+            type_name: std::any::type_name::<I>(),
+            name: None,
+            dependencies: vec![Dependency::of::<Arc<T>>("0")],
+            condition: None,
+        });
+        self.entities.insert(TypeId::of::<Arc<I>>());
+        self
+    }
+
+    /// Binds the interface `I` to `T`, as with [`bind`](#method.bind), only if `condition` holds
+    /// for the active [`Profile`] of the application.
+    pub fn bind_if<I, T>(self, condition: impl FnOnce(&Profile) -> bool + 'static) -> Self
+    where
+        I: Interface<T> + ?Sized + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        self.only_if(condition, |module| module.bind::<I, T>())
+    }
+
+    /// Contributes `T` to the implementations of the interface `I`, which are injected together
+    /// as `Vec<Arc<I>>`. `T` is provided as well unless it is already reachable from the module.
+    ///
+    /// Contributions are aggregated across modules: the implementations injected in a module are
+    /// the ones contributed by the module itself and by all modules it imports, directly or
+    /// transitively, without any explicit export.
+    ///
+    /// ## Example
+    ///
+    /// ```rust,no_run
+    /// use contraband::module::Module;
+    /// use contraband::{interface, Injectable};
+    /// use std::sync::Arc;
+    ///
+    /// #[interface]
+    /// trait HealthIndicator: Send + Sync {
+    ///     fn is_healthy(&self) -> bool;
+    /// }
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct DatabaseHealth;
+    ///
+    /// impl HealthIndicator for DatabaseHealth {
+    ///     fn is_healthy(&self) -> bool {
+    ///         true
+    ///     }
+    /// }
+    ///
+    /// #[derive(Clone, Injectable)]
+    /// struct HealthService {
+    ///     indicators: Vec<Arc<dyn HealthIndicator>>,
+    /// }
+    ///
+    /// let module = Module::new()
+    ///     .bind_multi::<dyn HealthIndicator, DatabaseHealth>()
+    ///     .provide::<HealthService>();
+    /// ```
+    pub fn bind_multi<I, T>(mut self) -> Self
+    where
+        I: Interface<T> + ?Sized + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        self = self.declare_multi::<I>();
+        self.providers.push(async_step(|module, ctx| {
+            let mut imported_graphs = vec![&ctx.global_providers];
+            for module in &module.imported_modules {
+                imported_graphs.push(&module.exported_graph);
+            }
+            let provided = module.graph.contains::<Arc<T>>()
+                || imported_graphs
+                    .iter()
+                    .any(|graph| graph.contains::<Arc<T>>());
+            let resolved = match module.graph.resolve::<Arc<T>>(&imported_graphs) {
+                Ok(instance) => Ok(instance.clone()),
+                Err(err) => Err(err),
+            };
+            let contributed = resolved.map(|instance| {
+                if !provided {
+                    ctx.lifecycle.register(T::hooks(&instance));
+                }
+                module.graph.contribute(I::upcast(instance));
+            });
+            ready(contributed).boxed_local()
+        }));
+        self.provider_nodes.push(ProviderNode {
+            type_id: TypeId::of::<Vec<Arc<I>>>(),
+            type_name: std::any::type_name::<I>(),
+            name: None,
+            dependencies: vec![Dependency::of::<Arc<T>>("0")],
+            condition: None,
+        });
+        self
+    }
+
+    /// Declares the interface `I` as multi-bound without contributing to it, so that
+    /// `Vec<Arc<I>>` is injected as an empty collection when no module contributes to `I`. The
+    /// interface is declared by [`bind_multi`](#method.bind_multi) as well, even if the
+    /// contribution is skipped for the active [`Profile`].
+    pub fn declare_multi<I>(mut self) -> Self
+    where
+        I: ?Sized + Send + Sync + 'static,
+    {
+        self.provider_values
+            .push(Box::new(|module| module.graph.declare_multi::<I>()));
+        self
+    }
+
+    /// Contributes `T` to the interface `I`, as with [`bind_multi`](#method.bind_multi), only if
+    /// `condition` holds for the active [`Profile`] of the application.
+    pub fn bind_multi_if<I, T>(self, condition: impl FnOnce(&Profile) -> bool + 'static) -> Self
+    where
+        I: Interface<T> + ?Sized + Send + Sync + 'static,
+        T: Injected<Output = T> + 'static,
+    {
+        self.only_if(condition, |module| module.bind_multi::<I, T>())
+    }
+
+    /// Skips the provider declared by `declare` unless `condition` holds for the active
+    /// [`Profile`] of the application.
+    fn only_if(
+        mut self,
+        condition: impl FnOnce(&Profile) -> bool + 'static,
+        declare: impl FnOnce(Self) -> Self,
+    ) -> Self {
+        let index = self.provider_nodes.len();
+        let entities = self.entities.clone();
+        self = declare(self);
+        self.provider_nodes[index].condition = Some(Box::new(condition));
+        let added: Vec<_> = self.entities.difference(&entities).copied().collect();
+        for entity in &added {
+            self.entities.remove(entity);
+        }
+        self.conditional_entities.insert(index, added);
+        self
+    }
+
+    pub fn controller<T>(mut self) -> Self
+    where
+        T: Injected<Output = T> + ControllerFactory + 'static,
+    {
+        self.controllers.push(Box::new(|module, ctx| {
+            let mut imported_graphs = vec![&ctx.global_providers];
+            for module in &module.imported_modules {
+                imported_graphs.push(&module.exported_graph);
+            }
+            let request_scoped = T::dependencies().iter().any(|dependency| {
+                dependency.name.is_none()
+                    && module
+                        .graph
+                        .is_request_scoped(dependency.type_id, &imported_graphs)
+            });
+            let instance = if request_scoped {
+                let resolution_scope = Arc::new(OnceCell::new());
+                module.unresolved_scopes.push(resolution_scope.clone());
+                ControllerInstance::Request(resolution_scope)
+            } else {
+                let controller = Arc::new(T::resolve(&mut module.graph, &imported_graphs)?);
+                ctx.lifecycle.register(T::hooks(&controller));
+                ControllerInstance::Singleton((*controller).clone())
+            };
+            let enhancers = T::enhancers(&mut module.graph, &imported_graphs)?;
+            module.controllers.push(Arc::new(ControllerProvider {
+                instance,
+                enhancers,
+                global_interceptors: ctx.interceptors.clone(),
+                module_filters: module.filters.clone(),
+            }));
+            Ok(())
+        }));
+        self.controller_descriptions.push(ControllerDescription {
+            type_name: std::any::type_name::<T>(),
+            routes: T::routes(),
+        });
+        self.entities.insert(TypeId::of::<T>());
+        self
+    }
+
+    /// Catches the errors of the route handlers of all controllers of the module with the
+    /// exception filter `F`, unless they are caught by a filter of the route or the controller.
+    /// See [`crate::exception`].
+    pub fn use_filter<F>(mut self) -> Self
+    where
+        F: ExceptionFilter + Injected<Output = F> + 'static,
+    {
+        self.filters.push(exception::resolve::<F>);
+        self
+    }
+
+    pub(crate) async fn build(self, ctx: &mut Context) -> Result<ResolvedModule, ResolutionError> {
+        let name = self.name;
+        let mut module = ResolvedModule::new();
+        module.global = self.global;
+        for import in self.imports {
+            import(&mut module, ctx).await?;
+        }
+        // Imported modules are in the order of `imported_factories`.
+        let mut reexported = Vec::new();
+        for (type_id, reexported_name) in self.reexported_modules {
+            let index = self
+                .imported_factories
+                .iter()
+                .position(|imported| imported.type_id == type_id)
+                .ok_or(ResolutionError::MissingImport {
+                    reexported: reexported_name,
+                    module: Some(name),
+                })?;
+            reexported.push(module.imported_modules[index].clone());
+        }
+        // Providers not declared for the active profile take no part in the module.
+        let profile = ctx
+            .global_providers
+            .get_node::<Profile>()
+            .cloned()
+            .unwrap_or_default();
+        let mut provider_nodes = Vec::new();
+        let mut providers = Vec::new();
+        let mut entities = self.entities;
+        let mut conditional_entities = self.conditional_entities;
+        for (index, (mut node, provider)) in self
+            .provider_nodes
+            .into_iter()
+            .zip(self.providers)
+            .enumerate()
+        {
+            if node.is_enabled(&profile) {
+                entities.extend(conditional_entities.remove(&index).into_iter().flatten());
+                provider_nodes.push(node);
+                providers.push(Some(provider));
+            }
+        }
+        let imported_graphs: Vec<&Graph> = std::iter::once(&ctx.global_providers)
+            .chain(
+                module
+                    .imported_modules
+                    .iter()
+                    .map(|module| &module.exported_graph),
+            )
+            .collect();
+        module.graph.merge_contributions(&imported_graphs);
+        module.graph.override_from(
+            &ctx.overrides.providers,
+            provider_nodes
+                .iter()
+                .map(|node| node.type_id)
+                .chain(entities.iter().copied()),
+        );
+        for provided_value in self.provider_values {
+            provided_value(&mut module);
+        }
+        let order = resolution_order(&provider_nodes).map_err(|chain| {
+            ResolutionError::DependencyCycle {
+                chain,
+                module: Some(name),
+            }
+        })?;
+        for index in order {
+            if let Some(provider) = providers[index].take() {
+                provider(&mut module, ctx)
+                    .await
+                    .map_err(|err| err.in_module(name))?;
+            }
+        }
+        let filters = self.filters;
+        module.filters = module
+            .with_graphs(&ctx.global_providers, |graph, imports| {
+                filters
+                    .iter()
+                    .map(|resolve| resolve(graph, imports))
+                    .collect::<Result<_, _>>()
+            })
+            .map_err(|err| err.in_module(name))?;
+        for controller in self.controllers {
+            controller(&mut module, ctx).map_err(|err| err.in_module(name))?;
+        }
+        let mut imported_graphs = vec![&ctx.global_providers];
+        for imported_module in &module.imported_modules {
+            imported_graphs.push(&imported_module.exported_graph);
+        }
+        for deferred in module.graph.take_deferred() {
+            deferred
+                .resolve(&module.graph, &imported_graphs)
+                .map_err(|err| err.in_module(name))?;
+        }
+        let resolution_scope = ResolutionScope {
+            graph: module.graph.clone(),
+            imports: imported_graphs.into_iter().cloned().collect(),
+        };
+        for unresolved in module.unresolved_scopes.drain(..) {
+            let _ = unresolved.set(resolution_scope.clone());
+        }
+        ctx.lifecycle.init_module().await;
+        module.exported_graph = module
+            .graph
+            .filter_by(self.exported_providers)
+            .filter_named(&self.exported_names);
+        for imported in reexported {
+            module.exported_graph.merge(&imported.exported_graph);
+        }
+        Ok(module)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ResolvedModule {
+    pub(crate) graph: Graph,
+    pub(crate) imported_modules: Vec<Arc<Self>>,
+    exported_graph: Graph,
+    pub(crate) controllers: Vec<Arc<dyn ServiceFactory>>,
+    unresolved_scopes: Vec<Arc<OnceCell<ResolutionScope>>>,
+    /// The exception filters of the controllers of the module.
+    filters: Vec<Arc<dyn Catch>>,
+    global: bool,
+}
+
+impl ResolvedModule {
+    pub fn new() -> Self {
+        Self {
+            graph: Graph::new(),
+            exported_graph: Graph::new(),
+            imported_modules: Vec::new(),
+            controllers: Vec::new(),
+            unresolved_scopes: Vec::new(),
+            filters: Vec::new(),
+            global: false,
+        }
+    }
+
+    /// Calls `f` with the graph of the module and the graphs reachable from it, e.g. in order to
+    /// resolve an instance from the root module once it has been built.
+    pub(crate) fn with_graphs<R>(
+        &mut self,
+        global_providers: &Graph,
+        f: impl FnOnce(&mut Graph, &[&Graph]) -> R,
+    ) -> R {
+        let mut imported_graphs = vec![global_providers];
+        for imported_module in &self.imported_modules {
+            imported_graphs.push(&imported_module.exported_graph);
+        }
+        f(&mut self.graph, &imported_graphs)
+    }
+}
+
+pub trait ModuleFactory: Sized {
+    fn get_module() -> Module;
+}
+
+/// Builds `module`, or the module overriding it, naming it after its type in resolution errors.
+///
+/// Returns [`ResolutionError::ImportCycle`] if the module is already being built further up the
+/// import chain. Dynamic modules are told apart from the static module of the same type, but not
+/// from other dynamic modules of the same type, since their factories cannot be compared.
+pub(crate) fn build_module_ref(
+    ctx: &mut Context,
+    module: ModuleRef,
+) -> LocalBoxFuture<'_, Result<ResolvedModule, ResolutionError>> {
+    async move {
+        let ModuleRef {
+            type_id,
+            name,
+            factory,
+            dynamic,
+        } = ctx.overrides.module(module);
+        let key = (type_id, dynamic);
+        if let Some(pos) = ctx.import_chain.iter().position(|(id, _)| *id == key) {
+            let mut chain: Vec<_> = ctx.import_chain[pos..].iter().map(|(_, x)| *x).collect();
+            chain.push(name);
+            return Err(ResolutionError::ImportCycle { chain });
+        }
+        ctx.import_chain.push((key, name));
+        let mut module = factory();
+        module.name = name;
+        let resolved = module.build(ctx).await;
+        ctx.import_chain.pop();
+        resolved
+    }
+    .boxed_local()
+}
+
+/// Builds the global `module`, unless it has already been built as an import of another global
+/// module, and provides its exports to all modules.
+pub(crate) async fn provide_global_module(
+    ctx: &mut Context,
+    module: ModuleRef,
+) -> Result<(), ResolutionError> {
+    let type_id = module.type_id;
+    let resolved = match ctx.modules.get(&type_id) {
+        Some(resolved) => resolved.clone(),
+        None => {
+            let resolved = Arc::new(build_module_ref(ctx, module).await?);
+            ctx.modules.insert(type_id, resolved.clone());
+            resolved
+        }
+    };
+    ctx.global_providers.merge(&resolved.exported_graph);
+    Ok(())
+}
+
+/// The declaration of a module together with the declarations of the modules it imports, taking
+/// module overrides into account. The tree is created once from the module factories in order to
+/// inspect the modules of an application before they are built. Imports that would close an
+/// import cycle are left out.
+pub(crate) struct DeclaredModule {
+    /// The module as imported, before module overrides are applied.
+    module: ModuleRef,
+    /// The name of the module, or of the module overriding it.
+    name: &'static str,
+    declared: Module,
+    imports: Vec<Self>,
+}
+
+impl DeclaredModule {
+    pub(crate) fn new(root: ModuleRef, overrides: &Overrides) -> Self {
+        fn visit(
+            module: ModuleRef,
+            overrides: &Overrides,
+            chain: &mut Vec<TypeId>,
+        ) -> DeclaredModule {
+            let ModuleRef {
+                type_id,
+                name,
+                factory,
+                ..
+            } = overrides.module(module.clone());
+            let declared = factory();
+            chain.push(type_id);
+            let mut imports = Vec::new();
+            for imported in &declared.imported_factories {
+                if !chain.contains(&overrides.module(imported.clone()).type_id) {
+                    imports.push(visit(imported.clone(), overrides, chain));
+                }
+            }
+            chain.pop();
+            DeclaredModule {
+                module,
+                name,
+                declared,
+                imports,
+            }
+        }
+
+        visit(root, overrides, &mut Vec::new())
+    }
+
+    /// Collects the global modules transitively imported by the module. Modules come after the
+    /// global modules they import.
+    pub(crate) fn global_modules(&self) -> Vec<ModuleRef> {
+        fn visit(
+            module: &DeclaredModule,
+            visited: &mut HashSet<TypeId>,
+            globals: &mut Vec<ModuleRef>,
+        ) {
+            if !visited.insert(module.module.type_id) {
+                return;
+            }
+            for imported in &module.imports {
+                visit(imported, visited, globals);
+            }
+            if module.declared.global {
+                globals.push(module.module.clone());
+            }
+        }
+
+        let mut visited = HashSet::new();
+        visited.insert(self.module.type_id);
+        let mut globals = Vec::new();
+        for imported in &self.imports {
+            visit(imported, &mut visited, &mut globals);
+        }
+        globals
+    }
+
+    /// Collects the configuration sections declared by the module and all modules it transitively
+    /// imports.
+    pub(crate) fn sections(&self) -> Vec<SectionDeclaration> {
+        fn visit(
+            module: &DeclaredModule,
+            visited: &mut HashSet<TypeId>,
+            sections: &mut Vec<SectionDeclaration>,
+        ) {
+            if !visited.insert(module.module.type_id) {
+                return;
+            }
+            for section in &module.declared.config_sections {
+                let mut section = *section;
+                section.module = Some(module.name);
+                sections.push(section);
+            }
+            for imported in &module.imports {
+                visit(imported, visited, sections);
+            }
+        }
+
+        let mut sections = Vec::new();
+        visit(self, &mut HashSet::new(), &mut sections);
+        sections
+    }
+
+    /// Describes the module tree with the providers declared for `profile`. No providers are
+    /// resolved.
+    pub(crate) fn describe(self, profile: &Profile) -> ModuleDescription {
+        self.describe_with(profile, &mut HashMap::new())
+    }
+
+    /// Describes the module, identifying the instances of dynamic modules by their number among
+    /// the instances of their type in `ids`.
+    fn describe_with(
+        self,
+        profile: &Profile,
+        ids: &mut HashMap<(TypeId, Option<usize>), String>,
+    ) -> ModuleDescription {
+        let Self {
+            module,
+            name,
+            declared,
+            imports,
+        } = self;
+        let key = module.key();
+        let instances = ids
+            .keys()
+            .filter(|(type_id, instance)| *type_id == key.0 && instance.is_some())
+            .count();
+        let id = ids
+            .entry(key)
+            .or_insert_with(|| match key.1 {
+                Some(_) => format!("{}#{}", name, instances + 1),
+                None => name.to_string(),
+            })
+            .clone();
+        let providers = declared
+            .values
+            .into_iter()
+            .chain(
+                declared
+                    .provider_nodes
+                    .into_iter()
+                    .filter_map(|mut node| {
+                        if node.is_enabled(profile) {
+                            Some(node)
+                        } else {
+                            None
+                        }
+                    })
+                    .map(|node| ProviderDescription {
+                        type_name: node.type_name,
+                        name: node.name,
+                        dependencies: node
+                            .dependencies
+                            .iter()
+                            .map(|dependency| dependency.type_name)
+                            .collect(),
+                    }),
+            )
+            .collect();
+        ModuleDescription {
+            id,
+            name,
+            global: declared.global,
+            imports: imports
+                .into_iter()
+                .map(|imported| imported.describe_with(profile, ids))
+                .collect(),
+            providers,
+            exports: declared.exported_type_names,
+            named_exports: declared.exported_names,
+            controllers: declared.controller_descriptions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as contraband;
+    use crate::config::{SectionError, Settings};
+    use crate::graph::{Lazy, Value};
+    use crate::module::Module;
+    use crate::Injectable;
+
+    fn get_empty_ctx() -> Context {
+        Context::new(Graph::new())
+    }
+
+    #[contraband::test]
+    async fn test_provide_value_get_resolved() {
+        let value = Value("test_str");
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide_value(value)
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(
             **resolved.graph.get_node::<Value<&str>>().unwrap(),
             "test_str"
         );
     }
 
-    #[test]
-    fn test_imported_value_is_reachable() {
+    #[contraband::test]
+    async fn test_imported_value_is_reachable() {
         struct ExportingModule;
         impl ModuleFactory for ExportingModule {
             fn get_module() -> Module {
@@ -189,7 +1748,11 @@ mod tests {
         }
 
         let mut ctx = get_empty_ctx();
-        let resolved = Module::new().import::<ExportingModule>().build(&mut ctx);
+        let resolved = Module::new()
+            .import::<ExportingModule>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
         assert_eq!(resolved.imported_modules.len(), 1);
         assert_eq!(
             **resolved.imported_modules[0]
@@ -200,8 +1763,8 @@ mod tests {
         );
     }
 
-    #[test]
-    fn test_imported_provider_is_reachable() {
+    #[contraband::test]
+    async fn test_imported_provider_is_reachable() {
         #[derive(Clone, Injectable)]
         struct ToTest;
 
@@ -213,11 +1776,1073 @@ mod tests {
         }
 
         let mut ctx = get_empty_ctx();
-        let resolved = Module::new().import::<ExportingModule>().build(&mut ctx);
+        let resolved = Module::new()
+            .import::<ExportingModule>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
         assert_eq!(resolved.imported_modules.len(), 1);
         assert!(resolved.imported_modules[0]
             .graph
             .get_node::<Arc<ToTest>>()
             .is_some());
     }
+
+    #[contraband::test]
+    async fn test_dynamic_modules_are_built_with_parameters() {
+        struct GreetingModule;
+        impl GreetingModule {
+            fn for_feature(greeting: &'static str) -> DynamicModule {
+                DynamicModule::new::<Self>(move || {
+                    Module::new()
+                        .export_value::<Value<&'static str>>()
+                        .provide_value(Value(greeting))
+                })
+            }
+        }
+
+        struct EnglishModule;
+        impl ModuleFactory for EnglishModule {
+            fn get_module() -> Module {
+                Module::new().import_dynamic(GreetingModule::for_feature("Hello"))
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<EnglishModule>()
+            .import_dynamic(GreetingModule::for_feature("Hallo"))
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let greeting =
+            |module: &ResolvedModule| **module.exported_graph.get_node::<Value<&str>>().unwrap();
+        assert_eq!(
+            greeting(&resolved.imported_modules[0].imported_modules[0]),
+            "Hello"
+        );
+        assert_eq!(greeting(&resolved.imported_modules[1]), "Hallo");
+    }
+
+    #[contraband::test]
+    async fn test_dynamic_modules_are_not_shared_with_static_modules() {
+        struct GreetingModule;
+        impl ModuleFactory for GreetingModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .export_value::<Value<&'static str>>()
+                    .provide_value(Value("Hi"))
+            }
+        }
+
+        impl GreetingModule {
+            fn for_feature(greeting: &'static str) -> DynamicModule {
+                DynamicModule::new::<Self>(move || {
+                    Module::new()
+                        .import::<Self>()
+                        .export_value::<Value<&'static str>>()
+                        .provide_value(Value(greeting))
+                })
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<GreetingModule>()
+            .import_dynamic(GreetingModule::for_feature("Hallo"))
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let greeting =
+            |module: &ResolvedModule| **module.exported_graph.get_node::<Value<&str>>().unwrap();
+        assert_eq!(greeting(&resolved.imported_modules[0]), "Hi");
+        assert_eq!(greeting(&resolved.imported_modules[1]), "Hallo");
+        assert!(Arc::ptr_eq(
+            &resolved.imported_modules[0],
+            &resolved.imported_modules[1].imported_modules[0]
+        ));
+    }
+
+    #[contraband::test]
+    async fn test_reexported_module_is_reachable() {
+        #[derive(Clone, Injectable)]
+        struct Cache;
+
+        struct CacheModule;
+        impl ModuleFactory for CacheModule {
+            fn get_module() -> Module {
+                Module::new().export::<Cache>().provide::<Cache>()
+            }
+        }
+
+        struct InfrastructureModule;
+        impl ModuleFactory for InfrastructureModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .import::<CacheModule>()
+                    .reexport::<CacheModule>()
+            }
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Service {
+            cache: Arc<Cache>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<InfrastructureModule>()
+            .provide::<Service>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let cache_module = &resolved.imported_modules[0].imported_modules[0];
+        assert!(Arc::ptr_eq(
+            &resolved.graph.get_node::<Arc<Service>>().unwrap().cache,
+            cache_module.graph.get_node::<Arc<Cache>>().unwrap()
+        ));
+
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .reexport::<CacheModule>()
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            ResolutionError::MissingImport {
+                reexported: std::any::type_name::<CacheModule>(),
+                module: Some("<anonymous module>"),
+            }
+        );
+    }
+
+    #[contraband::test]
+    async fn test_missing_provider_is_reported() {
+        #[derive(Clone, Injectable)]
+        struct Dependency;
+
+        // Never resolved, the field is only named by the error.
+        #[allow(dead_code)]
+        #[derive(Clone, Injectable)]
+        struct Dependent {
+            dependency: Arc<Dependency>,
+        }
+
+        struct DependentModule;
+        impl ModuleFactory for DependentModule {
+            fn get_module() -> Module {
+                Module::new().provide::<Dependent>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .import::<DependentModule>()
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            ResolutionError::MissingProvider {
+                type_name: std::any::type_name::<Arc<Dependency>>(),
+                name: None,
+                required_by: Some(std::any::type_name::<Dependent>()),
+                field: Some("dependency"),
+                module: Some(std::any::type_name::<DependentModule>()),
+            }
+        );
+    }
+
+    #[contraband::test]
+    async fn test_circular_import_is_reported() {
+        struct FirstModule;
+        impl ModuleFactory for FirstModule {
+            fn get_module() -> Module {
+                Module::new().import::<SecondModule>()
+            }
+        }
+
+        struct SecondModule;
+        impl ModuleFactory for SecondModule {
+            fn get_module() -> Module {
+                Module::new().import::<FirstModule>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let err = build_module_ref(&mut ctx, ModuleRef::of::<FirstModule>())
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            ResolutionError::ImportCycle {
+                chain: vec![
+                    std::any::type_name::<FirstModule>(),
+                    std::any::type_name::<SecondModule>(),
+                    std::any::type_name::<FirstModule>(),
+                ],
+            }
+        );
+    }
+
+    #[contraband::test]
+    async fn test_circular_provider_dependency_is_reported() {
+        #[derive(Clone, Injectable)]
+        struct First {
+            _second: Arc<Second>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Second {
+            _first: Arc<First>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .provide::<First>()
+            .provide::<Second>()
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            ResolutionError::DependencyCycle {
+                chain: vec![
+                    std::any::type_name::<First>(),
+                    std::any::type_name::<Second>(),
+                    std::any::type_name::<First>(),
+                ],
+                module: Some("<anonymous module>"),
+            }
+        );
+    }
+
+    #[contraband::test]
+    async fn test_providers_are_resolved_in_dependency_order() {
+        #[derive(Clone, Injectable)]
+        struct Repository;
+
+        #[derive(Clone, Injectable)]
+        struct Service {
+            repository: Arc<Repository>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Controller {
+            service: Arc<Service>,
+            greeter: Arc<dyn Greeter>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Controller>()
+            .provide::<Service>()
+            .bind::<dyn Greeter, EnglishGreeter>()
+            .provide::<Repository>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let controller = resolved.graph.get_node::<Arc<Controller>>().unwrap();
+        assert!(Arc::ptr_eq(
+            &controller.service,
+            resolved.graph.get_node::<Arc<Service>>().unwrap()
+        ));
+        assert!(Arc::ptr_eq(
+            &controller.service.repository,
+            resolved.graph.get_node::<Arc<Repository>>().unwrap()
+        ));
+        assert_eq!(controller.greeter.greet(), "Hello");
+    }
+
+    #[contraband::test]
+    async fn test_lazy_dependency_is_resolved_after_build() {
+        #[derive(Clone, Injectable)]
+        struct First {
+            second: Lazy<Second>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Second {
+            first: Arc<First>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<First>()
+            .provide::<Second>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let first = resolved.graph.get_node::<Arc<First>>().unwrap();
+        let second = resolved.graph.get_node::<Arc<Second>>().unwrap();
+        assert!(Arc::ptr_eq(first.second.get().unwrap(), second));
+        assert!(Arc::ptr_eq(&second.first, first));
+    }
+
+    #[contraband::test]
+    async fn test_async_factory_is_awaited() {
+        #[derive(Clone, Injectable)]
+        struct Dependent {
+            value: Value<u32>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide_value(Value("test_str"))
+            .provide_factory_async(|(value,): (Value<&str>,)| async move {
+                Ok::<_, String>(Value(value.len() as u32))
+            })
+            .provide::<Dependent>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(
+            *resolved.graph.get_node::<Arc<Dependent>>().unwrap().value,
+            8
+        );
+    }
+
+    #[contraband::test]
+    async fn test_missing_factory_dependency_is_reported() {
+        #[derive(Clone, Injectable)]
+        struct Unprovided;
+
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .provide_factory_async(|(_,): (Arc<Unprovided>,)| async {
+                Ok::<_, String>(Value(0u32))
+            })
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            ResolutionError::MissingProvider {
+                type_name: std::any::type_name::<Arc<Unprovided>>(),
+                name: None,
+                required_by: Some(std::any::type_name::<Value<u32>>()),
+                field: Some("0"),
+                module: Some("<anonymous module>"),
+            }
+        );
+    }
+
+    #[contraband::test]
+    async fn test_failing_async_factory_is_reported() {
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .provide_factory_async(|()| async { Err::<Value<u32>, _>("connection refused") })
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            ResolutionError::FactoryFailed {
+                type_name: std::any::type_name::<Value<u32>>(),
+                message: "connection refused".to_string(),
+                module: Some("<anonymous module>"),
+            }
+        );
+    }
+
+    #[derive(serde::Deserialize)]
+    struct ServerSettings {
+        port: u16,
+    }
+
+    impl ConfigSection for ServerSettings {
+        const SECTION: &'static str = "server";
+    }
+
+    #[contraband::test]
+    async fn test_config_section_is_injected() {
+        #[derive(Clone, Injectable)]
+        struct Server {
+            settings: Config<ServerSettings>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide_value(Settings::from_toml("[server]\nport = 8080").unwrap())
+            .config::<ServerSettings>()
+            .provide::<Server>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(
+            resolved
+                .graph
+                .get_node::<Arc<Server>>()
+                .unwrap()
+                .settings
+                .port,
+            8080
+        );
+    }
+
+    #[contraband::test]
+    async fn test_invalid_config_section_is_reported() {
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .provide_value(Settings::from_toml("[server]\nport = \"http\"").unwrap())
+            .config::<ServerSettings>()
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        match err {
+            ResolutionError::InvalidConfig { errors, .. } => match errors.as_slice() {
+                [SectionError {
+                    section: "server",
+                    module: Some("<anonymous module>"),
+                    ..
+                }] => {}
+                errors => panic!("unexpected errors: {:?}", errors),
+            },
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[contraband::test]
+    async fn test_invalid_config_sections_are_reported_together() {
+        #[derive(serde::Deserialize)]
+        struct ClientSettings {
+            #[allow(dead_code)]
+            url: String,
+        }
+
+        impl ConfigSection for ClientSettings {
+            const SECTION: &'static str = "client";
+        }
+
+        struct SettingsModule;
+        impl ModuleFactory for SettingsModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .export_value::<Settings>()
+                    .provide_value(Settings::from_toml("[server]\nport = \"http\"").unwrap())
+            }
+        }
+
+        struct ServerModule;
+        impl ModuleFactory for ServerModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .import::<SettingsModule>()
+                    .config::<ServerSettings>()
+            }
+        }
+
+        struct ClientModule;
+        impl ModuleFactory for ClientModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .import::<SettingsModule>()
+                    .config::<ClientSettings>()
+            }
+        }
+
+        struct AppModule;
+        impl ModuleFactory for AppModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .import::<ServerModule>()
+                    .import::<ClientModule>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        ctx.unvalidated_sections =
+            DeclaredModule::new(ModuleRef::of::<AppModule>(), &Overrides::default()).sections();
+        let err = build_module_ref(&mut ctx, ModuleRef::of::<AppModule>())
+            .await
+            .err()
+            .unwrap();
+        match err {
+            ResolutionError::InvalidConfig { errors, .. } => {
+                let sections: Vec<_> = errors.iter().map(|x| (x.section, x.module)).collect();
+                assert_eq!(
+                    sections,
+                    vec![
+                        ("server", Some(std::any::type_name::<ServerModule>())),
+                        ("client", Some(std::any::type_name::<ClientModule>())),
+                    ]
+                );
+            }
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[contraband::test]
+    async fn test_provider_is_selected_by_profile() {
+        #[derive(Clone, Injectable)]
+        struct InMemoryMailer;
+
+        #[derive(Clone, Injectable)]
+        struct SmtpMailer;
+
+        let mut graph = Graph::new();
+        graph.provide(Arc::new(Profile::new("test")));
+        let mut ctx = Context::new(graph);
+        let resolved = Module::new()
+            .provide_if::<InMemoryMailer>(|profile| !profile.is("prod"))
+            .provide_if::<SmtpMailer>(|profile| profile.is("prod"))
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        assert!(resolved.graph.contains::<Arc<InMemoryMailer>>());
+        assert!(!resolved.graph.contains::<Arc<SmtpMailer>>());
+    }
+
+    #[contraband::test]
+    async fn test_disabled_provider_takes_no_part_in_dependency_cycles() {
+        #[derive(Clone, Injectable)]
+        struct Cache {
+            _store: Arc<Store>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Store {
+            cache: Option<Arc<Cache>>,
+        }
+
+        let mut graph = Graph::new();
+        graph.provide(Arc::new(Profile::new("test")));
+        let mut ctx = Context::new(graph);
+        let resolved = Module::new()
+            .provide_if::<Cache>(|profile| profile.is("prod"))
+            .provide::<Store>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let store = resolved.graph.get_node::<Arc<Store>>().unwrap();
+        assert!(store.cache.is_none());
+    }
+
+    #[contraband::interface]
+    trait Greeter: Send + Sync {
+        fn greet(&self) -> &'static str;
+    }
+
+    #[derive(Clone, Injectable)]
+    struct EnglishGreeter;
+
+    impl Greeter for EnglishGreeter {
+        fn greet(&self) -> &'static str {
+            "Hello"
+        }
+    }
+
+    #[contraband::test]
+    async fn test_interface_is_bound_to_implementation() {
+        #[derive(Clone, Injectable)]
+        struct Greeting {
+            greeter: Arc<dyn Greeter>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .bind::<dyn Greeter, EnglishGreeter>()
+            .provide::<Greeting>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(
+            resolved
+                .graph
+                .get_node::<Arc<Greeting>>()
+                .unwrap()
+                .greeter
+                .greet(),
+            "Hello"
+        );
+        assert!(resolved.graph.contains::<Arc<EnglishGreeter>>());
+    }
+
+    #[contraband::test]
+    async fn test_named_providers_are_injected_by_name() {
+        #[derive(Clone, Injectable)]
+        struct Repository {
+            primary: Value<&'static str>,
+            #[inject(name = "replica")]
+            replica: Value<&'static str>,
+        }
+
+        struct ReplicaModule;
+        impl ModuleFactory for ReplicaModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .export_named("replica")
+                    .provide_value_named("replica", Value("replica"))
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<ReplicaModule>()
+            .provide_value(Value("primary"))
+            .provide::<Repository>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let repository = resolved.graph.get_node::<Arc<Repository>>().unwrap();
+        assert_eq!(*repository.primary, "primary");
+        assert_eq!(*repository.replica, "replica");
+    }
+
+    #[contraband::test]
+    async fn test_missing_named_provider_is_reported() {
+        #[derive(Clone, Injectable)]
+        struct Repository {
+            #[inject(name = "replica")]
+            _replica: Value<&'static str>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .provide_value(Value("primary"))
+            .provide::<Repository>()
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("named `replica`"));
+    }
+
+    #[contraband::test]
+    async fn test_optional_dependencies_are_injected_if_provided() {
+        #[derive(Clone, Injectable)]
+        struct Metrics;
+
+        #[derive(Clone, Injectable)]
+        struct Service {
+            metrics: Option<Arc<Metrics>>,
+            #[inject(optional)]
+            annotated: Option<Arc<Metrics>>,
+            #[inject(name = "replica")]
+            replica: Option<Value<&'static str>>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Service>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let service = resolved.graph.get_node::<Arc<Service>>().unwrap();
+        assert!(service.metrics.is_none());
+        assert!(service.annotated.is_none());
+        assert!(service.replica.is_none());
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Metrics>()
+            .provide_value_named("replica", Value("replica"))
+            .provide::<Service>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let service = resolved.graph.get_node::<Arc<Service>>().unwrap();
+        assert!(service.metrics.is_some());
+        assert!(service.annotated.is_some());
+        assert_eq!(*service.replica.clone().unwrap(), "replica");
+    }
+
+    #[contraband::test]
+    async fn test_generic_tuple_struct_is_injected() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Clone, Injectable)]
+        struct Connection;
+
+        fn initial_capacity() -> usize {
+            16
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Repository<C: Clone>(
+            Arc<C>,
+            #[inject(default)] Arc<AtomicUsize>,
+            #[inject(with = "initial_capacity")] usize,
+        );
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide::<Connection>()
+            .provide::<Repository<Connection>>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let repository = resolved
+            .graph
+            .get_node::<Arc<Repository<Connection>>>()
+            .unwrap();
+        assert!(Arc::ptr_eq(
+            &repository.0,
+            resolved.graph.get_node::<Arc<Connection>>().unwrap()
+        ));
+        assert_eq!(repository.1.load(Ordering::SeqCst), 0);
+        assert_eq!(repository.2, 16);
+        assert_eq!(
+            Repository::<Connection>::dependencies(),
+            vec![Dependency::of::<Arc<Connection>>("0")]
+        );
+    }
+
+    #[derive(Clone, Injectable)]
+    struct GermanGreeter;
+
+    impl Greeter for GermanGreeter {
+        fn greet(&self) -> &'static str {
+            "Hallo"
+        }
+    }
+
+    #[contraband::test]
+    async fn test_multi_bindings_are_aggregated_across_modules() {
+        #[derive(Clone, Injectable)]
+        struct Greetings {
+            greeters: Vec<Arc<dyn Greeter>>,
+        }
+
+        struct GermanModule;
+        impl ModuleFactory for GermanModule {
+            fn get_module() -> Module {
+                Module::new().bind_multi::<dyn Greeter, GermanGreeter>()
+            }
+        }
+
+        struct IntermediateModule;
+        impl ModuleFactory for IntermediateModule {
+            fn get_module() -> Module {
+                Module::new().import::<GermanModule>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .import::<GermanModule>()
+            .import::<IntermediateModule>()
+            .bind_multi::<dyn Greeter, EnglishGreeter>()
+            .provide::<Greetings>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let greetings: Vec<_> = resolved
+            .graph
+            .get_node::<Arc<Greetings>>()
+            .unwrap()
+            .greeters
+            .iter()
+            .map(|greeter| greeter.greet())
+            .collect();
+        assert_eq!(greetings, vec!["Hallo", "Hello"]);
+    }
+
+    #[contraband::test]
+    async fn test_multi_bindings_without_contributions_are_injected_empty() {
+        #[derive(Clone, Injectable)]
+        struct Greetings {
+            greeters: Vec<Arc<dyn Greeter>>,
+        }
+
+        struct PluginModule;
+        impl ModuleFactory for PluginModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .bind_multi_if::<dyn Greeter, GermanGreeter>(|profile| profile.is("prod"))
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        ctx.global_providers.provide(Arc::new(Profile::new("test")));
+        let resolved = Module::new()
+            .import::<PluginModule>()
+            .provide::<Greetings>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        assert!(resolved
+            .graph
+            .get_node::<Arc<Greetings>>()
+            .unwrap()
+            .greeters
+            .is_empty());
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .declare_multi::<dyn Greeter>()
+            .provide::<Greetings>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        assert!(resolved
+            .graph
+            .get_node::<Arc<Greetings>>()
+            .unwrap()
+            .greeters
+            .is_empty());
+    }
+
+    #[contraband::test]
+    async fn test_transient_provider_is_created_per_dependent() {
+        #[derive(Clone, Injectable)]
+        struct Transient;
+
+        #[derive(Clone, Injectable)]
+        struct First {
+            transient: Arc<Transient>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Second {
+            transient: Arc<Transient>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let resolved = Module::new()
+            .provide_scoped::<Transient>(Scope::Transient)
+            .provide::<First>()
+            .provide::<Second>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        let first = resolved.graph.get_node::<Arc<First>>().unwrap();
+        let second = resolved.graph.get_node::<Arc<Second>>().unwrap();
+        assert!(!Arc::ptr_eq(&first.transient, &second.transient));
+    }
+
+    #[contraband::test]
+    async fn test_request_scoped_provider_is_not_injected_into_singleton() {
+        #[derive(Clone, Injectable)]
+        struct RequestScoped;
+
+        #[derive(Clone, Injectable)]
+        struct Singleton {
+            _request_scoped: Arc<RequestScoped>,
+        }
+
+        let mut ctx = get_empty_ctx();
+        let err = Module::new()
+            .provide_scoped::<RequestScoped>(Scope::Request)
+            .provide::<Singleton>()
+            .build(&mut ctx)
+            .await
+            .err()
+            .unwrap();
+        assert_eq!(
+            err,
+            ResolutionError::InvalidScope {
+                type_name: std::any::type_name::<Arc<RequestScoped>>(),
+                module: Some("<anonymous module>"),
+            }
+        );
+    }
+
+    #[contraband::test]
+    async fn test_request_scoped_provider_is_created_per_request() {
+        use crate::controller;
+        use crate::core::ContrabandApp;
+        use actix_web::{test, HttpResponse};
+
+        #[derive(Clone, Injectable)]
+        struct CurrentUser {
+            request: RequestContext,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct UserController {
+            current_user: Arc<CurrentUser>,
+        }
+
+        #[controller("user")]
+        impl UserController {
+            #[get]
+            async fn get_user(self) -> HttpResponse {
+                let name = self.current_user.request.header("x-user").unwrap_or("");
+                HttpResponse::Ok().body(name.to_string())
+            }
+        }
+
+        struct UserModule;
+        impl ModuleFactory for UserModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .provide_scoped::<CurrentUser>(Scope::Request)
+                    .controller::<UserController>()
+            }
+        }
+
+        let mut server = ContrabandApp::new().test_server::<UserModule>().await;
+        for name in &["alice", "bob"] {
+            let req = test::TestRequest::get()
+                .uri("/user")
+                .header("x-user", *name)
+                .to_request();
+            let body = test::read_response(&mut server, req).await;
+            assert_eq!(body, name.as_bytes());
+        }
+    }
+
+    #[contraband::test]
+    async fn test_controller_is_resolved_per_request_through_transient_providers() {
+        use crate::controller;
+        use crate::core::ContrabandApp;
+        use actix_web::{test, HttpResponse};
+
+        #[derive(Clone, Injectable)]
+        struct CurrentUser {
+            request: RequestContext,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Greeting {
+            current_user: Arc<CurrentUser>,
+        }
+
+        #[derive(Clone, Injectable)]
+        struct GreetingController {
+            greeting: Arc<Greeting>,
+        }
+
+        #[controller("greeting")]
+        impl GreetingController {
+            #[get]
+            async fn greet(self) -> HttpResponse {
+                let request = &self.greeting.current_user.request;
+                let name = request.header("x-user").unwrap_or("");
+                HttpResponse::Ok().body(format!("Hello {}", name))
+            }
+        }
+
+        struct GreetingModule;
+        impl ModuleFactory for GreetingModule {
+            fn get_module() -> Module {
+                Module::new()
+                    .provide_scoped::<CurrentUser>(Scope::Request)
+                    .provide_scoped::<Greeting>(Scope::Transient)
+                    .controller::<GreetingController>()
+            }
+        }
+
+        let mut server = ContrabandApp::new().test_server::<GreetingModule>().await;
+        for name in &["alice", "bob"] {
+            let req = test::TestRequest::get()
+                .uri("/greeting")
+                .header("x-user", *name)
+                .to_request();
+            let body = test::read_response(&mut server, req).await;
+            assert_eq!(body, format!("Hello {}", name).as_bytes());
+        }
+    }
+
+    #[contraband::test]
+    async fn test_hooks_of_imported_providers_are_registered_once() {
+        use crate::lifecycle::{async_trait, OnModuleInit};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        static INITIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone, Injectable)]
+        struct Connection;
+
+        #[async_trait(?Send)]
+        impl OnModuleInit for Connection {
+            async fn on_module_init(&self) {
+                INITIALIZED.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        struct DatabaseModule;
+        impl ModuleFactory for DatabaseModule {
+            fn get_module() -> Module {
+                Module::new().export::<Connection>().provide::<Connection>()
+            }
+        }
+
+        let mut ctx = get_empty_ctx();
+        Module::new()
+            .import::<DatabaseModule>()
+            .provide::<Connection>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        ctx.lifecycle.init_module().await;
+        assert_eq!(INITIALIZED.load(Ordering::SeqCst), 1);
+    }
+
+    #[contraband::test]
+    async fn test_lifecycle_hooks_are_called_in_dependency_order() {
+        use crate::lifecycle::{async_trait, OnApplicationShutdown, OnModuleInit};
+        use std::sync::Mutex;
+
+        type Events = Value<Arc<Mutex<Vec<&'static str>>>>;
+
+        #[derive(Clone, Injectable)]
+        struct First {
+            events: Events,
+        }
+
+        #[async_trait(?Send)]
+        impl OnModuleInit for First {
+            async fn on_module_init(&self) {
+                self.events.lock().unwrap().push("init first");
+            }
+        }
+
+        #[async_trait(?Send)]
+        impl OnApplicationShutdown for First {
+            async fn on_application_shutdown(&self) {
+                self.events.lock().unwrap().push("shutdown first");
+            }
+        }
+
+        #[derive(Clone, Injectable)]
+        struct Second {
+            _first: Arc<First>,
+            events: Events,
+        }
+
+        #[async_trait(?Send)]
+        impl OnModuleInit for Second {
+            async fn on_module_init(&self) {
+                self.events.lock().unwrap().push("init second");
+            }
+        }
+
+        #[async_trait(?Send)]
+        impl OnApplicationShutdown for Second {
+            async fn on_application_shutdown(&self) {
+                self.events.lock().unwrap().push("shutdown second");
+            }
+        }
+
+        let events: Arc<Mutex<Vec<&str>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut ctx = get_empty_ctx();
+        Module::new()
+            .provide_value(Value(events.clone()))
+            .provide::<First>()
+            .provide::<Second>()
+            .build(&mut ctx)
+            .await
+            .unwrap();
+        ctx.lifecycle.bootstrap().await;
+        ctx.lifecycle.shutdown().await;
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                "init first",
+                "init second",
+                "shutdown second",
+                "shutdown first"
+            ]
+        );
+    }
 }
@@ -0,0 +1,373 @@
+//! Pipes validating and transforming the parameters of route handlers
+//!
+//! Parameters of route handlers of a [controller](../attr.controller.html) are validated before
+//! the handler is called when they are annotated with `#[validate]`, which validates a parameter
+//! deriving [`Validate`](../derive.Validate.html), or with rules such as
+//! `#[validate(range(min = 1))]`. Parameters annotated with `#[pipe(...)]` are transformed by the
+//! given pipes, which are injectable providers implementing [`Pipe`], before they are validated.
+//!
+//! If any parameter is invalid the handler is not called, and the request is responded to with
+//! `400 Bad Request` as a JSON [`Problem`](crate::exception::Problem) listing the errors of all
+//! fields.
+//!
+//! ## Rules
+//!
+//! * `length(min = 1, max = 100)` for strings and collections, with either bound being optional
+//! * `range(min = 1, max = 100)` for numbers and other ordered values, with either bound being
+//!   optional
+//! * `custom = "path"` for a function `fn(&T) -> Result<(), String>`
+//! * no rules, i.e. `#[validate]`, for values implementing [`Validate`]
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use actix_web::{web, HttpResponse};
+//! use contraband::pipe::{Pipe, ValidationErrors};
+//! use contraband::{controller, Injectable, Validate};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize, Validate)]
+//! struct NewBookInput {
+//!     #[validate(length(min = 1, max = 200))]
+//!     title: String,
+//!     #[validate(length(min = 1))]
+//!     author: String,
+//! }
+//!
+//! #[derive(Clone, Injectable)]
+//! struct TrimPipe;
+//!
+//! impl Pipe<web::Json<NewBookInput>> for TrimPipe {
+//!     fn transform(
+//!         &self,
+//!         mut input: web::Json<NewBookInput>,
+//!     ) -> Result<web::Json<NewBookInput>, ValidationErrors> {
+//!         input.title = input.title.trim().to_string();
+//!         input.author = input.author.trim().to_string();
+//!         Ok(input)
+//!     }
+//! }
+//!
+//! #[derive(Clone, Injectable)]
+//! struct BookController;
+//!
+//! #[controller("/books")]
+//! impl BookController {
+//!     #[get("/{id}")]
+//!     async fn get(self, #[validate(range(min = 1))] id: web::Path<i32>) -> HttpResponse {
+//!         HttpResponse::Ok().finish()
+//!     }
+//!
+//!     #[post]
+//!     async fn add(
+//!         self,
+//!         #[pipe(TrimPipe)]
+//!         #[validate]
+//!         input: web::Json<NewBookInput>,
+//!     ) -> HttpResponse {
+//!         HttpResponse::Ok().finish()
+//!     }
+//! }
+//! ```
+use crate::exception::Problem;
+use crate::module::ControllerProvider;
+use actix_web::http::StatusCode;
+use actix_web::{HttpRequest, HttpResponse, ResponseError};
+use serde::Serialize;
+use std::any::TypeId;
+use std::fmt;
+
+/// A value that can be validated, usually derived with
+/// [`#[derive(Validate)]`](../derive.Validate.html).
+pub trait Validate {
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// Transforms and validates a parameter of type `T` of a route handler.
+pub trait Pipe<T>: Send + Sync {
+    fn transform(&self, value: T) -> Result<T, ValidationErrors>;
+}
+
+/// The error of an invalid field.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+/// The errors of all invalid fields of a value, responded to with `400 Bad Request`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ValidationErrors(Vec<FieldError>);
+
+impl ValidationErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, field: impl Into<String>, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+    }
+
+    /// Adds the errors of `other`.
+    pub fn extend(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    /// Adds the errors of `other`, which are the errors of the nested value `field`, e.g.
+    /// `address.street` for the field `street` of `address`.
+    pub fn nest(&mut self, field: &str, other: Self) {
+        self.0.extend(other.0.into_iter().map(|error| FieldError {
+            field: format!("{}.{}", field, error.field),
+            message: error.message,
+        }));
+    }
+
+    pub fn errors(&self) -> &[FieldError] {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns `Err(self)` if there are any errors.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Adds the errors of `result`, returning its value if there are none.
+    #[doc(hidden)]
+    pub fn collect<T>(&mut self, result: Result<T, Self>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(errors) => {
+                self.extend(errors);
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let errors: Vec<_> = self
+            .0
+            .iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect();
+        write!(f, "Validation failed: {}", errors.join(", "))
+    }
+}
+
+impl ResponseError for ValidationErrors {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::BAD_REQUEST
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        Problem::new(StatusCode::BAD_REQUEST)
+            .with_errors(self.0.clone())
+            .into()
+    }
+}
+
+/// The lengths of values validated by the `length` rule.
+pub trait Length {
+    fn length(&self) -> usize;
+}
+
+impl Length for str {
+    /// The number of characters of the string.
+    fn length(&self) -> usize {
+        self.chars().count()
+    }
+}
+
+impl Length for String {
+    fn length(&self) -> usize {
+        self.as_str().length()
+    }
+}
+
+impl<T> Length for [T] {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+impl<T> Length for Vec<T> {
+    fn length(&self) -> usize {
+        self.len()
+    }
+}
+
+/// The rules of `#[validate(...)]`, returning the message of the error if `value` is invalid.
+#[doc(hidden)]
+pub mod rules {
+    use super::Length;
+    use std::fmt::Display;
+
+    pub fn length<T: Length + ?Sized>(
+        value: &T,
+        min: Option<usize>,
+        max: Option<usize>,
+    ) -> Option<String> {
+        let length = value.length();
+        match (min, max) {
+            (Some(min), Some(max)) if length < min || length > max => {
+                Some(format!("length must be between {} and {}", min, max))
+            }
+            (Some(min), None) if length < min => Some(format!("length must be at least {}", min)),
+            (None, Some(max)) if length > max => Some(format!("length must be at most {}", max)),
+            _ => None,
+        }
+    }
+
+    pub fn range<T: PartialOrd + Display>(
+        value: &T,
+        min: Option<T>,
+        max: Option<T>,
+    ) -> Option<String> {
+        match (min, max) {
+            (Some(min), Some(max)) if *value < min || *value > max => {
+                Some(format!("must be between {} and {}", min, max))
+            }
+            (Some(min), None) if *value < min => Some(format!("must be at least {}", min)),
+            (None, Some(max)) if *value > max => Some(format!("must be at most {}", max)),
+            _ => None,
+        }
+    }
+}
+
+/// Transforms `value` with the pipe `P` of the controller `T` handling `req`.
+#[doc(hidden)]
+pub fn apply<T, P, V>(req: &HttpRequest, value: V) -> Result<V, ValidationErrors>
+where
+    T: 'static,
+    P: Pipe<V> + 'static,
+{
+    ControllerProvider::<T>::of(req)
+        .enhancers
+        .pipes
+        .get(&TypeId::of::<P>())
+        .downcast_ref::<P>()
+        .expect("Pipes are stored by their type")
+        .transform(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as contraband;
+    use crate::core::ContrabandApp;
+    use crate::module::{Module, ModuleFactory};
+    use crate::{controller, Injectable, Validate};
+    use actix_web::{test, web};
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Validate)]
+    struct AuthorInput {
+        #[validate(length(min = 1))]
+        name: String,
+    }
+
+    #[derive(Deserialize, Validate)]
+    struct NewBookInput {
+        #[validate(length(min = 1, max = 20))]
+        title: String,
+        #[validate(range(min = 1450))]
+        year: i32,
+        #[validate]
+        author: AuthorInput,
+    }
+
+    #[derive(Clone, Injectable)]
+    struct TrimPipe;
+
+    impl Pipe<web::Json<NewBookInput>> for TrimPipe {
+        fn transform(
+            &self,
+            mut input: web::Json<NewBookInput>,
+        ) -> Result<web::Json<NewBookInput>, ValidationErrors> {
+            input.title = input.title.trim().to_string();
+            Ok(input)
+        }
+    }
+
+    #[derive(Clone, Injectable)]
+    struct BookController;
+
+    #[controller("/books")]
+    impl BookController {
+        #[get("/{id}")]
+        async fn get(self, #[validate(range(min = 1))] id: web::Path<i32>) -> String {
+            id.to_string()
+        }
+
+        #[post]
+        async fn add(
+            self,
+            #[pipe(TrimPipe)]
+            #[validate]
+            input: web::Json<NewBookInput>,
+        ) -> Result<String, fmt::Error> {
+            Ok(input.into_inner().title)
+        }
+    }
+
+    struct AppModule;
+    impl ModuleFactory for AppModule {
+        fn get_module() -> Module {
+            Module::new().controller::<BookController>()
+        }
+    }
+
+    #[contraband::test]
+    async fn test_pipes_validate_parameters() {
+        let mut server = ContrabandApp::new().test_server::<AppModule>().await;
+
+        let req = test::TestRequest::get().uri("/books/0").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            test::read_body(res).await,
+            r#"{"status":400,"title":"Bad Request","errors":[{"field":"id","message":"must be at least 1"}]}"#
+        );
+
+        let req = test::TestRequest::get().uri("/books/3").to_request();
+        let res = test::call_service(&mut server, req).await;
+        assert_eq!(test::read_body(res).await, "3");
+
+        let book = |title: &str, year: i32, author: &str| {
+            test::TestRequest::post()
+                .uri("/books")
+                .set_json(&serde_json::json!({
+                    "title": title,
+                    "year": year,
+                    "author": { "name": author },
+                }))
+                .to_request()
+        };
+        let res = test::call_service(&mut server, book("  ", 1200, "")).await;
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = serde_json::from_slice(&test::read_body(res).await).unwrap();
+        let fields: Vec<_> = body["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|error| error["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["title", "year", "author.name"]);
+
+        let res = test::call_service(&mut server, book(" Dune ", 1965, "Herbert")).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(test::read_body(res).await, "Dune");
+    }
+}
@@ -0,0 +1,267 @@
+//! Utilities for testing modules
+//!
+//! A [`TestingModule`] builds the module tree of an application like
+//! [`ContrabandApp::test_server`](crate::core::ContrabandApp::test_server), but allows providers
+//! and whole modules to be replaced before any of their dependents are resolved, e.g. in order to
+//! test controllers without a database.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use contraband::testing::TestingModule;
+//! use contraband::{module, Injectable};
+//!
+//! #[derive(Clone, Injectable)]
+//! struct BookService;
+//!
+//! #[module]
+//! #[provider(BookService)]
+//! struct AppModule;
+//!
+//! #[contraband::test]
+//! async fn test_books() {
+//!     let server = TestingModule::from::<AppModule>()
+//!         .override_provider(BookService)
+//!         .compile()
+//!         .await;
+//! }
+//! ```
+use crate::core::ContrabandApp;
+use crate::lifecycle::Lifecycle;
+use crate::module::{Interface, ModuleFactory, ModuleRef, Overrides};
+use actix_web::dev::Service;
+use std::any::TypeId;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+/// Builder for a test server of a module tree with overridden providers and modules.
+pub struct TestingModule {
+    root: ModuleRef,
+    overrides: Overrides,
+    app: ContrabandApp,
+}
+
+impl TestingModule {
+    /// Creates a testing module for the module tree of `T`.
+    pub fn from<T: ModuleFactory + 'static>() -> Self {
+        Self {
+            root: ModuleRef::of::<T>(),
+            overrides: Overrides::default(),
+            app: ContrabandApp::new(),
+        }
+    }
+
+    /// Sets the application the test server is configured by, e.g. in order to set its profile.
+    pub fn set_app(mut self, app: ContrabandApp) -> Self {
+        self.app = app;
+        self
+    }
+
+    /// Replaces the provider of `T` in every module providing it with `instance`. Dependents of
+    /// the provider are injected with `Arc<T>` of `instance`.
+    pub fn override_provider<T: Send + Sync + 'static>(mut self, instance: T) -> Self {
+        self.overrides
+            .providers
+            .provide(Arc::new(Arc::new(instance)));
+        self
+    }
+
+    /// Replaces the implementation bound to the interface `I` with
+    /// [`Module::bind`](crate::module::Module::bind) in every module binding it.
+    pub fn override_binding<I, T>(mut self, instance: T) -> Self
+    where
+        I: Interface<T> + ?Sized + Send + Sync + 'static,
+    {
+        self.overrides
+            .providers
+            .provide(Arc::new(I::upcast(Arc::new(instance))));
+        self
+    }
+
+    /// Replaces a value of type `T`, such as one provided by
+    /// [`Module::provide_value`](crate::module::Module::provide_value) or
+    /// [`Module::provide_factory_async`](crate::module::Module::provide_factory_async), in every
+    /// module providing it. Factories of overridden values are not run.
+    pub fn override_value<T: Send + Sync + 'static>(mut self, value: T) -> Self {
+        self.overrides.providers.provide(Arc::new(value));
+        self
+    }
+
+    /// Builds module `M` wherever module `T` is imported.
+    pub fn override_module<T, M>(mut self) -> Self
+    where
+        T: ModuleFactory + 'static,
+        M: ModuleFactory + 'static,
+    {
+        self.overrides
+            .modules
+            .insert(TypeId::of::<T>(), ModuleRef::of::<M>());
+        self
+    }
+
+    /// Resolves the module tree and returns a service for handling test requests.
+    ///
+    /// Panics if the module tree cannot be resolved.
+    pub async fn compile(
+        self,
+    ) -> TestServer<
+        impl Service<
+            Response = actix_web::dev::ServiceResponse,
+            Request = actix_http::Request,
+            Error = actix_web::Error,
+        >,
+    > {
+        self.app.test_server_with(self.root, self.overrides).await
+    }
+}
+
+/// A service handling test requests with the controllers of an application, returned by
+/// [`TestingModule::compile`] and
+/// [`ContrabandApp::test_server`](crate::core::ContrabandApp::test_server).
+///
+/// The application shutdown hooks are called by [`shutdown`](#method.shutdown). A test server
+/// dropped without being shut down calls them in the background, if the actix system is still
+/// running.
+pub struct TestServer<S> {
+    service: S,
+    lifecycle: Option<Lifecycle>,
+}
+
+impl<S> TestServer<S> {
+    pub(crate) fn new(service: S, lifecycle: Lifecycle) -> Self {
+        Self {
+            service,
+            lifecycle: Some(lifecycle),
+        }
+    }
+
+    /// Calls the application shutdown hooks, e.g. in order to release resources or to test
+    /// their behavior.
+    pub async fn shutdown(mut self) {
+        if let Some(mut lifecycle) = self.lifecycle.take() {
+            lifecycle.shutdown().await;
+        }
+    }
+}
+
+impl<S: Service> Service for TestServer<S> {
+    type Request = S::Request;
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Self::Request) -> Self::Future {
+        self.service.call(req)
+    }
+}
+
+impl<S> Drop for TestServer<S> {
+    fn drop(&mut self) {
+        if let Some(mut lifecycle) = self.lifecycle.take() {
+            if actix_rt::System::is_set() {
+                actix_rt::spawn(async move { lifecycle.shutdown().await });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as contraband;
+    use crate::graph::Value;
+    use crate::module::Module;
+    use crate::{controller, Injectable};
+    use actix_web::{test, HttpResponse};
+
+    #[derive(Clone, Injectable)]
+    struct GreetingService {
+        greeting: Value<&'static str>,
+    }
+
+    #[derive(Clone, Injectable)]
+    struct GreetingController {
+        service: Arc<GreetingService>,
+    }
+
+    #[controller]
+    impl GreetingController {
+        #[get]
+        async fn greet(self) -> HttpResponse {
+            HttpResponse::Ok().body(*self.service.greeting)
+        }
+    }
+
+    struct GreetingModule;
+    impl ModuleFactory for GreetingModule {
+        fn get_module() -> Module {
+            Module::new()
+                .export::<GreetingService>()
+                .provide_value(Value("Hello"))
+                .provide::<GreetingService>()
+        }
+    }
+
+    struct MockGreetingModule;
+    impl ModuleFactory for MockGreetingModule {
+        fn get_module() -> Module {
+            Module::new()
+                .export::<GreetingService>()
+                .provide_value(Value("Hi"))
+                .provide::<GreetingService>()
+        }
+    }
+
+    struct AppModule;
+    impl ModuleFactory for AppModule {
+        fn get_module() -> Module {
+            Module::new()
+                .import::<GreetingModule>()
+                .controller::<GreetingController>()
+        }
+    }
+
+    async fn greet(
+        server: &mut impl Service<
+            Response = actix_web::dev::ServiceResponse,
+            Request = actix_http::Request,
+            Error = actix_web::Error,
+        >,
+    ) -> actix_web::web::Bytes {
+        let req = test::TestRequest::get().uri("/").to_request();
+        test::read_response(server, req).await
+    }
+
+    #[contraband::test]
+    async fn test_override_provider() {
+        let mut server = TestingModule::from::<AppModule>()
+            .override_provider(GreetingService {
+                greeting: Value("Hey"),
+            })
+            .compile()
+            .await;
+        assert_eq!(greet(&mut server).await, "Hey");
+    }
+
+    #[contraband::test]
+    async fn test_override_value() {
+        let mut server = TestingModule::from::<AppModule>()
+            .override_value(Value("Howdy"))
+            .compile()
+            .await;
+        assert_eq!(greet(&mut server).await, "Howdy");
+    }
+
+    #[contraband::test]
+    async fn test_override_module() {
+        let mut server = TestingModule::from::<AppModule>()
+            .override_module::<GreetingModule, MockGreetingModule>()
+            .compile()
+            .await;
+        assert_eq!(greet(&mut server).await, "Hi");
+    }
+}